@@ -0,0 +1,30 @@
+// Generates the move tables at build time, so at runtime they are plain statics.
+// The generation code is shared with the crate, not everything in these modules is used here.
+#![allow(dead_code)]
+
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+#[path = "src/bit_help.rs"]
+mod bit_help;
+#[path = "src/magic.rs"]
+mod magic;
+#[path = "src/table_gen.rs"]
+mod table_gen;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/bit_help.rs");
+    println!("cargo:rerun-if-changed=src/magic.rs");
+    println!("cargo:rerun-if-changed=src/table_gen.rs");
+
+    let tables = table_gen::generate();
+    assert!(tables.verify(), "Generated slider tables don't match the slow move generation");
+
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("move_tables.rs");
+    let mut out = BufWriter::new(File::create(out_path).unwrap());
+    let with_pext = env::var_os("CARGO_FEATURE_PEXT").is_some();
+    tables.write_source(&mut out, with_pext).unwrap();
+}
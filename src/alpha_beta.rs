@@ -1,252 +1,851 @@
-use crate::two_player_game::Game;
 use crate::two_player_game::Scored;
+use crate::two_player_game::{Game, SearchMove, Searchable};
 use crate::two_player_game::GameState::PLAYING;
 use crate::two_player_game::Player::PLAYER1;
-use std::cmp::{max, min};
-use std::collections::HashMap;
+use std::cmp::{max, min, Reverse};
+use std::fmt::Display;
 use std::collections::hash_map::RandomState;
-use std::hash::{BuildHasher, Hasher};
 use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use ahash::AHashMap;
 use rand::rngs::ThreadRng;
-use rand::seq::{IteratorRandom, SliceRandom};
-use crate::chess_impl::{Chess, Move};
-use crate::get_time;
+use rand::seq::SliceRandom;
+use rand::{rngs::StdRng, SeedableRng};
+use crate::chess_impl::Chess;
+use crate::transposition::{TranspositionTable, Bound, DEFAULT_HASH_MB};
+use crate::move_ordering::MoveOrdering;
+use crate::time_manager::{TimeControl, TimeManager};
+use crate::search_limits::SearchLimits;
+use crate::search_observer::{IterationInfo, SearchObserver};
+use crate::search_trace::{Cutoff, SearchTrace};
+use crate::engine::Engine;
 
-type Cache = HashMap<u64, i32, A>;
-type Caches = HashMap<usize, Cache, A>;
+// Everything the search carries between nodes.
+pub struct SearchContext<'a, G: Searchable = Chess> {
+    pub ordering: MoveOrdering,
+    pub call_count: u64,
+    // Nodes searched in quiescence, included in call_count.
+    pub quiescence_count: u64,
+    // The search is abandoned at the deadline, after node_limit nodes, or when the stop flag is set from another thread.
+    pub deadline: Option<Instant>,
+    pub node_limit: Option<u64>,
+    pub stop: Arc<AtomicBool>,
+    stopped: bool,
+    // SearchMove::hash of the root moves to search, all when empty.
+    pub search_moves: Vec<u64>,
+    pub tt: &'a TranspositionTable,
+    pub options: SearchOptions,
 
+    // Game length at the root, the ply of a node is its distance from it.
+    pub root_len: usize,
+    // Deepest ply reached, including quiescence.
+    pub seldepth: usize,
+    // Triangular principal variation table - pv[ply] is the best line found from the node at ply.
+    pub pv: Vec<Vec<G::MoveType>>,
+    // The best root moves of the last root search, best first, options.multi_pv of them.
+    pub lines: Vec<PvLine<G>>,
+    // Only the main thread has one.
+    pub observer: Option<&'a mut dyn SearchObserver<G>>,
+    // Breaks ties between root moves, see TieBreak.
+    pub rng: StdRng,
+    // Records the alpha_beta tree, for debugging.
+    pub trace: Option<SearchTrace>,
+}
+
+impl<'a, G: Searchable> SearchContext<'a, G> {
+    pub fn new(tt: &'a TranspositionTable) -> SearchContext<'a, G> {
+        SearchContext {
+            ordering: MoveOrdering::new(),
+            call_count: 0,
+            quiescence_count: 0,
+            deadline: None,
+            node_limit: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            stopped: false,
+            search_moves: vec![],
+            tt,
+            options: SearchOptions::default(),
+            root_len: 0,
+            seldepth: 0,
+            pv: vec![],
+            lines: vec![],
+            observer: None,
+            rng: StdRng::from_entropy(),
+            trace: None,
+        }
+    }
+
+    // Whether to abandon the search. Looking at the clock and the flag costs, so only every CHECK_STOP_NODES nodes.
+    // The node limit is exact.
+    #[inline]
+    fn should_stop(&mut self) -> bool {
+        if self.node_limit.is_some_and(|n| self.call_count >= n) {
+            self.stopped = true;
+        }
+        if !self.stopped && self.call_count.is_multiple_of(CHECK_STOP_NODES) {
+            self.stopped = self.stop.load(Ordering::Relaxed) || self.deadline.is_some_and(|d| Instant::now() >= d);
+        }
+        self.stopped
+    }
+
+    fn cutoff(&mut self, ply: usize, cutoff: Cutoff) {
+        if let Some(trace) = &mut self.trace {
+            trace.cutoff(ply, cutoff);
+        }
+    }
+
+    // Start a node at ply - clears its line and makes room for its children.
+    fn enter_node(&mut self, ply: usize) {
+        self.seldepth = max(self.seldepth, ply);
+        if self.pv.len() < ply + 2 {
+            self.pv.resize(ply + 2, vec![]);
+        }
+        self.pv[ply].clear();
+        self.pv[ply + 1].clear();
+    }
+
+    // The move at ply is the new best, its line is the move followed by the child's line.
+    fn update_pv(&mut self, ply: usize, m: G::MoveType) {
+        let (parent, child) = self.pv.split_at_mut(ply + 1);
+        parent[ply].clear();
+        parent[ply].push(m);
+        parent[ply].extend(child[0].iter().cloned());
+    }
+}
+
+// Mate at ply p scores MAX_SCORE - p, being mated -MAX_SCORE + p. Scores past mate_bound are mates.
+const MAX_PLY: i32 = 1000;
 
-pub struct A {
+#[inline]
+fn mate_bound<G: Scored<ScoreType = i32>>() -> i32 {
+    debug_assert!(G::MIN_SCORE == -G::MAX_SCORE && G::MAX_SCORE > MAX_PLY, "scores don't fit the mate scoring");
+    G::MAX_SCORE - MAX_PLY
+}
 
+// Score for the player to move being checkmated at ply.
+#[inline]
+fn mated_score<G: Scored<ScoreType = i32>>(ply: usize) -> i32 {
+    G::MIN_SCORE + ply as i32
 }
 
-pub struct H {
-    state: u64
+// Draws are worth -contempt to the player at the root, so with a positive contempt it plays on in equal positions.
+#[inline]
+fn draw_score<G: Searchable>(ply: usize, ctx: &SearchContext<G>) -> i32 {
+    if ply.is_multiple_of(2) { -ctx.options.contempt } else { ctx.options.contempt }
 }
 
-impl BuildHasher for A {
-    type Hasher = H;
+#[inline]
+fn is_mate_score<G: Scored<ScoreType = i32>>(score: i32) -> bool {
+    score.abs() >= mate_bound::<G>()
+}
 
-    fn build_hasher(&self) -> Self::Hasher {
-        H { state: 0 }
+// The table is shared by nodes at different plies, so it holds mate scores relative to the stored node.
+fn score_to_tt<G: Scored<ScoreType = i32>>(score: i32, ply: usize) -> i32 {
+    if score >= mate_bound::<G>() {
+        score + ply as i32
+    } else if score <= -mate_bound::<G>() {
+        score - ply as i32
+    } else {
+        score
     }
 }
 
-impl Hasher for H {
-    fn finish(&self) -> u64 {
-        return self.state;
+fn score_from_tt<G: Scored<ScoreType = i32>>(score: i32, ply: usize) -> i32 {
+    if score >= mate_bound::<G>() {
+        score - ply as i32
+    } else if score <= -mate_bound::<G>() {
+        score + ply as i32
+    } else {
+        score
     }
+}
 
-    fn write(&mut self, bytes: &[u8]) {
-        todo!()
+// Moves until mate for a score from the root - positive when the player to move mates, negative when it's mated.
+pub fn mate_in<G: Scored<ScoreType = i32>>(score: i32) -> Option<i32> {
+    if score >= mate_bound::<G>() {
+        Some((G::MAX_SCORE - score + 1) / 2)
+    } else if score <= -mate_bound::<G>() {
+        Some(-(G::MAX_SCORE + score) / 2)
+    } else {
+        None
     }
+}
 
-    fn write_u64(&mut self, i: u64) {
-        self.state = i;
+pub fn score_string<G: Scored<ScoreType = i32>>(score: i32) -> String {
+    match mate_in::<G>(score) {
+        Some(n) if n > 0 => format!("mate in {}", n),
+        Some(n) => format!("mated in {}", -n),
+        None => score.to_string(),
     }
+}
 
-    fn write_usize(&mut self, i: usize) {
-        self.state = i as u64;
+// Nodes between looks at the clock and the stop flag.
+const CHECK_STOP_NODES: u64 = 1024;
+
+// Depth searched to when the limits don't say.
+const MAX_DEPTH: i32 = 100;
+
+// Half width of the first aspiration window, in centipawns.
+const ASPIRATION_WINDOW: i32 = 30;
+
+const NULL_MOVE_MIN_DEPTH: i32 = 3;
+const NULL_MOVE_REDUCTION: i32 = 2;
+const LMR_MIN_DEPTH: i32 = 3;
+// Moves before this (hash move, killers) are never reduced.
+const LMR_MIN_MOVE: usize = 3;
+const REVERSE_FUTILITY_DEPTH: i32 = 3;
+const REVERSE_FUTILITY_MARGIN: i32 = 120;
+// By remaining depth, futility pruning is only done at depth 1 and 2.
+const FUTILITY_MARGIN: [i32; 3] = [0, 200, 350];
+// Positional gain a capture may make on top of the material, for delta pruning.
+const DELTA_MARGIN: i32 = 200;
+
+// Forward pruning techniques, each can be switched off to measure what it's worth in self-play. And the value of a draw.
+#[derive(Clone, Copy, Debug)]
+pub struct SearchOptions {
+    pub null_move_pruning: bool,
+    pub late_move_reductions: bool,
+    pub reverse_futility_pruning: bool,
+    pub futility_pruning: bool,
+    // Search all moves when in check during quiescence, instead of standing pat.
+    pub quiescence_check_evasions: bool,
+    pub delta_pruning: bool,
+    // Centipawns the engine gives up to avoid a draw. Negative to seek draws.
+    pub contempt: i32,
+    // Search threads, sharing the transposition table.
+    pub threads: usize,
+    // Root moves to find an exact score and line for, for analysis. Only the best is played.
+    pub multi_pv: usize,
+    pub tie_break: TieBreak,
+    // Record the main thread's tree down to this ply, for debugging. See MoveResult::trace.
+    pub trace_ply: Option<usize>,
+}
+
+// How the move to play is picked from root moves with the same score.
+// SEEDED and FIRST give the same move for the same position and options, as long as the search isn't cut short by time
+// or a stop, and runs on one thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TieBreak {
+    RANDOM,
+    SEEDED(u64),
+    // The first in search order.
+    FIRST,
+}
+
+fn tie_break_rng(tie_break: TieBreak) -> StdRng {
+    match tie_break {
+        TieBreak::SEEDED(seed) => StdRng::seed_from_u64(seed),
+        _ => StdRng::from_entropy(),
     }
 }
 
-pub struct MoveResult {
-    pub chess_move: Option<<Chess as Game>::MoveType>,
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            null_move_pruning: true,
+            late_move_reductions: true,
+            reverse_futility_pruning: true,
+            futility_pruning: true,
+            quiescence_check_evasions: true,
+            delta_pruning: true,
+            contempt: 0,
+            threads: 1,
+            multi_pv: 1,
+            tie_break: TieBreak::RANDOM,
+            trace_ply: None,
+        }
+    }
+}
+
+pub struct MoveResult<G: Game = Chess> {
+    pub chess_move: Option<G::MoveType>,
+    // Depth of the last completed iteration.
     pub move_from_depth: i32,
+    // The expected line, starting with chess_move.
+    pub pv: Vec<G::MoveType>,
+    // From the point of view of the player to move.
+    pub score: i32,
+    pub seldepth: usize,
+    // Nodes searched in all iterations, including an unfinished last one.
+    pub nodes: u64,
+    pub time: Duration,
+    // The best root moves, best first. The first is chess_move and pv, there are more with options.multi_pv.
+    pub lines: Vec<PvLine<G>>,
+    // With options.trace_ply, the tree of the last completed root search.
+    pub trace: Option<SearchTrace>,
+}
+
+// A root move with its score and line, from the point of view of the player to move.
+pub struct PvLine<G: Game = Chess> {
+    pub score: i32,
+    // Starts with the root move.
+    pub pv: Vec<G::MoveType>,
+}
+
+impl<G: Game> Clone for PvLine<G> where G::MoveType: Clone {
+    fn clone(&self) -> Self {
+        PvLine { score: self.score, pv: self.pv.clone() }
+    }
 }
 
-pub fn get_next_move(game: &mut Chess, depth: i32, max_timestamp_ms: u128) -> MoveResult {
+pub fn pv_string(pv: &[impl Display]) -> String {
+    pv.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+// A search from scratch, use an Engine to keep what was learned between moves.
+#[allow(dead_code)]
+pub fn get_next_move<G: Searchable>(game: &mut G, limits: &SearchLimits<G::MoveType>, observer: &mut dyn SearchObserver<G>) -> MoveResult<G> {
+    Engine::new(DEFAULT_HASH_MB, SearchOptions::default()).search(game, limits, observer)
+}
 
-    let mut killer_move_cache: Caches = HashMap::with_capacity_and_hasher(depth as usize, A {});
-    let mut m = None;
-    let mut total_count = 0;
-    let mut move_from_depth = 0;
-    let mut game_copy = game.clone();
+// Searches with one thread per move ordering, the first is the main thread.
+// Setting stop ends the search early, with the result of the last completed iteration.
+pub fn get_next_move_with_tt<G: Searchable>(game: &mut G, limits: &SearchLimits<G::MoveType>, tt: &mut TranspositionTable, orderings: &mut [MoveOrdering], options: SearchOptions, stop: Arc<AtomicBool>, observer: &mut dyn SearchObserver<G>) -> MoveResult<G> {
 
+    tt.new_search();
+    let tt = &*tt;
+    for ordering in orderings.iter_mut() {
+        ordering.new_search();
+    }
+    let (main_ordering, helper_orderings) = orderings.split_first_mut().expect("a search needs a thread");
+    let depth = limits.depth.unwrap_or(MAX_DEPTH).min(MAX_DEPTH);
+    let mut time = TimeManager::new(if limits.infinite { TimeControl::default() } else { limits.time_control });
+    let deadline = time.hard_limit().map(|hard| Instant::now() + hard);
+    let search_moves: Vec<u64> = limits.search_moves.iter().map(|m| m.hash()).collect();
+    let mate = limits.mate;
+    // Helpers search until the main thread is done.
+    let helpers_stop = Arc::new(AtomicBool::new(false));
     let start = Instant::now();
-    for i in min(4,depth)..depth+1 {
-        let call_count: &mut i32 = &mut 0;
-        let ores = _get_next_move(&mut game_copy, i, &mut killer_move_cache, call_count, max_timestamp_ms);
+
+    let results = std::thread::scope(|s| {
+        let helpers: Vec<_> = helper_orderings.iter_mut().zip(1..).map(|(ordering, i)| {
+            let mut game = game.clone();
+            let helpers_stop = helpers_stop.clone();
+            let search_moves = search_moves.clone();
+            s.spawn(move || {
+                let mut ctx = SearchContext::new(tt);
+                ctx.deadline = deadline;
+                ctx.stop = helpers_stop;
+                ctx.search_moves = search_moves;
+                ctx.options = options;
+                ctx.rng = tie_break_rng(options.tie_break);
+                ctx.root_len = game.get_game_len();
+                ctx.ordering = std::mem::replace(ordering, MoveOrdering::new());
+                // Every other helper starts a ply deeper, so the threads don't all search the same tree in the same order.
+                let result = iterative_deepening(&mut game, min(4, depth) + i % 2, depth, mate, &mut ctx, None);
+                *ordering = ctx.ordering;
+                result
+            })
+        }).collect();
+
+        let mut ctx = SearchContext::new(tt);
+        ctx.deadline = deadline;
+        ctx.node_limit = limits.nodes;
+        ctx.stop = stop;
+        ctx.search_moves = search_moves;
+        ctx.options = options;
+        ctx.rng = tie_break_rng(options.tie_break);
+        ctx.root_len = game.get_game_len();
+        ctx.observer = Some(&mut *observer);
+        ctx.trace = options.trace_ply.map(SearchTrace::new);
+        ctx.ordering = std::mem::replace(main_ordering, MoveOrdering::new());
+        // A node limit can be too small for depth 4, start from 1 to have a move.
+        let start_depth = if limits.nodes.is_some() { 1 } else { min(4, depth) };
+        let main = iterative_deepening(&mut game.clone(), start_depth, depth, mate, &mut ctx, Some(&mut time));
+        *main_ordering = ctx.ordering;
+        helpers_stop.store(true, Ordering::Relaxed);
+
+        let mut results = vec![main];
+        results.extend(helpers.into_iter().map(|h| h.join().unwrap()));
+        results
+    });
+
+    let nodes = results.iter().map(|r| r.nodes).sum();
+    // The deepest completed iteration of any thread, then the best score. The main thread wins ties.
+    let best = results.into_iter().enumerate()
+        .filter(|(_, r)| r.chess_move.is_some())
+        .max_by_key(|(i, r)| (r.depth, r.score, Reverse(*i)))
+        .map(|(_, r)| r);
+
+    let result = match best {
+        Some(r) => MoveResult {
+            chess_move: r.chess_move,
+            move_from_depth: r.depth,
+            pv: r.pv,
+            score: r.score,
+            seldepth: r.seldepth,
+            nodes,
+            time: start.elapsed(),
+            lines: r.lines,
+            trace: r.trace,
+        },
+        // Stopped before the first iteration finished, any legal move beats none. The transposition table's if it has one.
+        None => {
+            let mut moves = game.possible_moves();
+            moves.retain(|m| limits.search_moves.is_empty() || limits.search_moves.iter().any(|s| s.hash() == m.hash()));
+            let tt_move = tt.probe(game.get_hash()).and_then(|e| e.best_move);
+            let fallback = moves.iter().position(|m| Some(m.hash()) == tt_move).unwrap_or(0);
+            let chess_move = (!moves.is_empty()).then(|| moves.swap_remove(fallback));
+            MoveResult {
+                chess_move: chess_move.clone(),
+                move_from_depth: 0,
+                pv: chess_move.into_iter().collect(),
+                score: G::NEUTRAL_SCORE,
+                seldepth: 0,
+                nodes,
+                time: start.elapsed(),
+                lines: vec![],
+                trace: None,
+            }
+        }
+    };
+    observer.search_finished(&result);
+    result
+}
+
+// The last completed iteration of one thread.
+struct ThreadResult<G: Searchable> {
+    chess_move: Option<G::MoveType>,
+    pv: Vec<G::MoveType>,
+    score: i32,
+    seldepth: usize,
+    depth: i32,
+    nodes: u64,
+    lines: Vec<PvLine<G>>,
+    trace: Option<SearchTrace>,
+}
+
+// Searches depth by depth, until depth, a mate in mate moves or until stopped.
+// Only the main thread has a time manager.
+fn iterative_deepening<G: Searchable>(game: &mut G, start_depth: i32, depth: i32, mate: Option<i32>, ctx: &mut SearchContext<G>, mut time: Option<&mut TimeManager>) -> ThreadResult<G> {
+    let mut result = ThreadResult { chess_move: None, pv: vec![], score: G::NEUTRAL_SCORE, seldepth: 0, depth: 0, nodes: 0, lines: vec![], trace: None };
+
+    let start = Instant::now();
+    for i in start_depth..depth+1 {
+        ctx.seldepth = 0;
+        let ores = aspiration_search(game, i, result.score, result.depth > 0, ctx);
         if let Some(res) = ores {
-            if res.0.is_some() {
-                m = res.0;
-                move_from_depth = i;
+            if let Some(m_) = res.0 {
+                if let Some(time) = time.as_mut() {
+                    time.iteration_finished(m_.hash(), res.1);
+                }
+                result.chess_move = Some(m_);
+                result.pv = ctx.pv[0].clone();
+                result.score = res.1;
+                result.seldepth = ctx.seldepth;
+                result.depth = i;
+                result.lines = ctx.lines.clone();
             }
-            total_count += *call_count;
-            let nps = total_count as f64 / start.elapsed().as_secs_f64();
-            eprintln!("Depth: {}, Move: {}, Score: {}, CallCount: {}, Total: {}, NPS: {}", i, m.clone().unwrap(), res.1, call_count, total_count, nps as u64);
+            if let Some(trace) = &mut ctx.trace {
+                result.trace = Some(std::mem::replace(trace, SearchTrace::new(trace.max_ply)));
+            }
+            let hashfull = ctx.tt.hashfull();
+            if let Some(observer) = ctx.observer.as_mut() {
+                let time = start.elapsed();
+                observer.iteration_finished(&IterationInfo {
+                    depth: i,
+                    seldepth: ctx.seldepth,
+                    score: res.1,
+                    pv: &result.pv,
+                    lines: &result.lines,
+                    nodes: ctx.call_count,
+                    nps: (ctx.call_count as f64 / time.as_secs_f64()) as u64,
+                    hashfull,
+                    time,
+                });
+            }
+        } else {
+            break;
+        }
+
+        if time.as_ref().is_some_and(|t| t.should_stop()) {
+            break;
+        }
+        if result.chess_move.is_some() && mate.is_some_and(|n| mate_in::<G>(result.score).is_some_and(|m| m > 0 && m <= n)) {
+            break;
         }
     }
-    // let mut cache = killer_move_cache.iter().collect::<Vec<_>>();
-    // cache.sort_unstable_by_key(|(k, v)| *k );
-    // for (key, value) in cache {
-    //
-    //     let mut moves = value.iter().collect::<Vec<_>>();
-    //     moves.sort_unstable_by_key(|(_,&v)| -v);
-    //     let moves = moves.iter().map(|(m, c)| m.to_string() + ": " + &c.to_string()).collect::<Vec<_>>();
-    //     eprintln!("{}: {:?}", key, moves);
-    // }
-    MoveResult {
-        chess_move: m,
-        move_from_depth
+
+    result.nodes = ctx.call_count;
+    result
+}
+
+// Search the root in a narrow window around the previous iteration's score, widening it on failure.
+// Not with multi_pv, the lines after the first would fail low.
+fn aspiration_search<G: Searchable>(game: &mut G, depth: i32, prev_score: i32, has_prev_score: bool, ctx: &mut SearchContext<G>) -> Option<(Option<G::MoveType>, i32)> {
+    let mut delta = ASPIRATION_WINDOW;
+    let (mut a, mut b) = if has_prev_score && ctx.options.multi_pv <= 1 {
+        (prev_score.saturating_sub(delta).max(G::MIN_INFINITY), prev_score.saturating_add(delta))
+    } else {
+        (G::MIN_INFINITY, G::MAX_INFINITY)
+    };
+
+    loop {
+        let res = _get_next_move(game, depth, a, b, ctx)?;
+        if res.1 <= a && a > G::MIN_INFINITY {
+            a = res.1.saturating_sub(delta).max(G::MIN_INFINITY);
+        } else if res.1 >= b && b < G::MAX_INFINITY {
+            b = res.1.saturating_add(delta);
+        } else {
+            return Some(res);
+        }
+        delta = delta.saturating_mul(4);
     }
 }
 
+// Evaluation from the point of view of the player to move.
 #[inline]
-fn move_ordering(m: &Move, killer_move_cache_at_depth: &Cache) -> i32 {
-    -max(*killer_move_cache_at_depth.get(&m.hash()).unwrap_or(&0), (m.eaten_loc != 0) as i32 * 10)
+fn evaluate<G: Searchable>(game: &G) -> i32 {
+    let score = game.get_score();
+    debug_assert!(score.abs() < mate_bound::<G>(), "evaluation {} is in the mate range", score);
+    if game.current_player() == PLAYER1 { score } else { -score }
 }
 
-fn _get_next_move(game: &mut Chess, depth: i32, mut killer_move_cache: &mut Caches, call_count: &mut i32, max_timestamp_ms: u128) -> Option<(Option<<Chess as Game>::MoveType>, <Chess as Scored>::ScoreType)>
+// Returns the best move and its score in the window (a, b), from the point of view of the player to move.
+// The best options.multi_pv moves go to ctx.lines. Ties for the last of them are searched exactly too,
+// and ordered by options.tie_break.
+fn _get_next_move<G: Searchable>(game: &mut G, depth: i32, a: i32, b: i32, ctx: &mut SearchContext<G>) -> Option<(Option<G::MoveType>, i32)>
 {
-    let mut rng = rand::thread_rng();
-    let mut best_moves = vec![];
-    let mut score;
-    let mut a = Chess::MIN_INFINITY;
-    let mut b = Chess::MAX_INFINITY;
+    let Some(trace) = &mut ctx.trace else {
+        return root_search(game, depth, a, b, ctx);
+    };
+    // Only the last root search is kept, with the root as the first node.
+    *trace = SearchTrace::new(trace.max_ply);
+    trace.enter(0, None::<&G::MoveType>, depth, a, b, ctx.call_count);
+    let res = root_search(game, depth, a, b, ctx);
+    ctx.trace.as_mut().unwrap().exit(res.as_ref().map(|(_, score)| *score), ctx.call_count);
+    res
+}
+
+fn root_search<G: Searchable>(game: &mut G, depth: i32, a: i32, b: i32, ctx: &mut SearchContext<G>) -> Option<(Option<G::MoveType>, i32)>
+{
+    let multi_pv = ctx.options.multi_pv.max(1);
+    // (score, move, the child's line), best first.
+    let mut best_moves: Vec<(i32, G::MoveType, Vec<G::MoveType>)> = vec![];
 
     let mut possible_moves = game.possible_moves();
-    *call_count += 1;
-    let at_depth = killer_move_cache.entry(game.get_game_len()).or_insert(HashMap::with_hasher(A {}));
-    possible_moves.sort_by_cached_key(|m| move_ordering(m, at_depth));
-
-    if game.current_player() == PLAYER1 {
-        score = Chess::MIN_INFINITY + (game.get_game_len() * 100) as <Chess as Scored>::ScoreType;
-        for m in possible_moves {
-            let to = m.to;
-            game.do_move(m);
-            let move_score = alpha_beta(game, depth - 1, a, b, to, &mut killer_move_cache, call_count, max_timestamp_ms)?;
-            let m_ = game.undo_move();
-            if move_score >= score {
-                if move_score > score {
-                    // eprintln!("{}, {}", m_, move_score);
-                    best_moves.clear();
-                }
-                best_moves.push(m_);
-                score = move_score
-            }
-            a = max(a, score);
+    if !ctx.search_moves.is_empty() {
+        possible_moves.retain(|m| ctx.search_moves.contains(&m.hash()));
+    }
+    ctx.call_count += 1;
+    ctx.enter_node(0);
+
+    ctx.lines.clear();
+    if possible_moves.is_empty() {
+        ctx.cutoff(0, Cutoff::NOMOVES);
+        return Some((None, if game.is_lost() { mated_score::<G>(0) } else { draw_score(0, ctx) }));
+    }
+
+    // The root entry only holds the single best of all moves, not that of a restricted search or more lines.
+    // It is never a cutoff here, that would cut the principal variation short.
+    let store_root = multi_pv == 1 && ctx.search_moves.is_empty();
+    let key = game.get_hash();
+    let tt_entry = ctx.tt.probe(key);
+    let tt_move = tt_entry.and_then(|e| e.best_move);
+
+    let ordering = &ctx.ordering;
+    possible_moves.sort_by_cached_key(|m| -ordering.score(game, 0, m, tt_move, None));
+
+    for (i, m) in possible_moves.into_iter().enumerate() {
+        if let Some(observer) = ctx.observer.as_mut() {
+            observer.current_move(depth, &m, i + 1);
         }
-    } else {
-        score = Chess::MAX_INFINITY - (game.get_game_len() * 100) as <Chess as Scored>::ScoreType;
-        for m in possible_moves {
-            let to = m.to;
-            game.do_move(m);
-            let move_score = alpha_beta(game, depth - 1, a, b, to, &mut killer_move_cache, call_count, max_timestamp_ms)?;
-            let m_ = game.undo_move();
-            if move_score <= score {
-                if move_score < score {
-                    // eprintln!("{}, {}", m_, move_score);
-                    best_moves.clear();
-                }
-                best_moves.push(m_);
-                score = move_score
+        // A child that returns before writing its line would leave the previous sibling's.
+        ctx.pv[1].clear();
+        game.do_move(m);
+        let move_score = if best_moves.len() < multi_pv {
+            -alpha_beta(game, depth - 1, -b, -a, ctx)?
+        } else {
+            // Zero window just below the last line's score - anything that fails high ties or beats it.
+            let tie_a = max(a, best_moves[multi_pv - 1].0) - 1;
+            let mut move_score = -alpha_beta(game, depth - 1, -tie_a - 1, -tie_a, ctx)?;
+            if move_score > tie_a && move_score < b {
+                move_score = -alpha_beta(game, depth - 1, -b, -tie_a, ctx)?;
             }
-            b = min(b, score);
+            move_score
+        };
+        let m_ = game.undo_move();
+
+        if best_moves.len() < multi_pv || move_score >= best_moves[multi_pv - 1].0 {
+            best_moves.push((move_score, m_, ctx.pv[1].clone()));
+            best_moves.sort_by_key(|(s, _, _)| Reverse(*s));
+            // Keep the ties for the last line.
+            let last = best_moves[min(multi_pv, best_moves.len()) - 1].0;
+            best_moves.retain(|(s, _, _)| *s >= last);
+        }
+        if best_moves.len() >= multi_pv && best_moves[multi_pv - 1].0 >= b {
+            break;
         }
     }
 
-    let i = (0..best_moves.len()).choose(&mut rng);
+    if ctx.options.tie_break != TieBreak::FIRST {
+        best_moves.shuffle(&mut ctx.rng);
+    }
+    // Stable, ties stay in search order or shuffled.
+    best_moves.sort_by_key(|(s, _, _)| Reverse(*s));
+    best_moves.truncate(multi_pv);
+    ctx.lines = best_moves.into_iter().map(|(score, m_, child_pv)| {
+        let mut pv = vec![m_];
+        pv.extend(child_pv);
+        PvLine { score, pv }
+    }).collect();
 
-    let m = i.map(|x| best_moves.swap_remove(x));
-    if let Some(m_) = m.clone() {
-        *killer_move_cache.entry(game.get_game_len()).or_insert(HashMap::with_hasher(A {})).entry(m_.hash()).or_insert(0) += 1;
+    let score = ctx.lines.first().map_or(mated_score::<G>(0), |l| l.score);
+    let m = ctx.lines.first().map(|l| l.pv[0].clone());
+    ctx.pv[0] = ctx.lines.first().map(|l| l.pv.clone()).unwrap_or_default();
+    if score >= b {
+        ctx.cutoff(0, Cutoff::BETA);
+    }
+    if store_root {
+        ctx.tt.store(key, depth, score_to_tt::<G>(score, 0), bound_for(score, a, b), m.as_ref().map(|m_| m_.hash()));
     }
     return Some((m, score));
 }
 
-pub fn min_max(game: &mut Chess, depth: i32, last_to: u64) -> <Chess as Scored>::ScoreType {
-    let mut possible_moves = game.possible_moves();
+#[inline]
+fn bound_for(score: i32, a: i32, b: i32) -> Bound {
+    if score >= b {
+        Bound::LOWER
+    } else if score <= a {
+        Bound::UPPER
+    } else {
+        Bound::EXACT
+    }
+}
+
+// Negamax principal variation search. Scores are from the point of view of the player to move.
+// Fail-soft - a score <= a is an upper bound, and a score >= b is a lower bound.
+pub fn alpha_beta<G: Searchable>(game: &mut G, depth: i32, a: i32, b: i32, ctx: &mut SearchContext<G>) -> Option<i32>
+{
+    let Some(trace) = &mut ctx.trace else {
+        return alpha_beta_node(game, depth, a, b, ctx);
+    };
+    let ply = game.get_game_len() - ctx.root_len;
+    let traced = trace.enter(ply, game.last_move().filter(|_| ply > 0), depth, a, b, ctx.call_count);
+    let score = alpha_beta_node(game, depth, a, b, ctx);
+    if traced {
+        ctx.trace.as_mut().unwrap().exit(score, ctx.call_count);
+    }
+    score
+}
+
+fn alpha_beta_node<G: Searchable>(game: &mut G, depth: i32, mut a: i32, mut b: i32, ctx: &mut SearchContext<G>) -> Option<i32>
+{
+    let ply = game.get_game_len() - ctx.root_len;
+    if ply > 0 && game.is_draw() {
+        ctx.cutoff(ply, Cutoff::DRAW);
+        return Some(draw_score(ply, ctx));
+    }
 
     if depth <= 0 {
-        // Quiescence.
-        possible_moves.retain(|m| m.eaten_loc != 0 && m.eaten_loc == last_to);
-        if possible_moves.len() == 0 {
-            return game.get_score();
+        ctx.cutoff(ply, Cutoff::QUIESCENCE);
+        return quiescence(game, a, b, ctx);
+    }
+
+    if ctx.should_stop() {
+        return None;
+    }
+
+    ctx.call_count += 1;
+    ctx.enter_node(ply);
+    let pv_node = b.saturating_sub(a) > 1;
+
+    // Mate distance pruning - no line from here beats mating right away, or is worse than being mated right away.
+    a = max(a, mated_score::<G>(ply));
+    b = min(b, -mated_score::<G>(ply + 1));
+    if a >= b {
+        ctx.cutoff(ply, Cutoff::MATEDISTANCE);
+        return Some(a);
+    }
+
+    let key = game.get_hash();
+    let mut tt_move = None;
+    if let Some(entry) = ctx.tt.probe(key) {
+        tt_move = entry.best_move;
+        let tt_score = score_from_tt::<G>(entry.score, ply);
+        // Cutting off in pv nodes would cut the principal variation short.
+        if !pv_node && entry.depth >= depth {
+            let cutoff = match entry.bound {
+                Bound::EXACT => true,
+                Bound::LOWER => tt_score >= b,
+                Bound::UPPER => tt_score <= a,
+            };
+            if cutoff {
+                ctx.cutoff(ply, Cutoff::TRANSPOSITION);
+                return Some(tt_score);
+            }
         }
     }
 
-    let mut score;
+    let in_check = game.is_in_check();
+    let static_eval = evaluate::<G>(game);
+    let prune = !pv_node && !in_check;
 
-    if game.current_player() == PLAYER1 {
-        score = Chess::MIN_INFINITY;
-        for m in possible_moves {
-            let to = m.eaten_loc;
-            game.do_move(m);
-            score = max(score, min_max(game, depth - 1, to));
-            game.undo_move();
+    // Reverse futility - so far above b that no move is going to drop below it.
+    if prune && ctx.options.reverse_futility_pruning && depth <= REVERSE_FUTILITY_DEPTH && !is_mate_score::<G>(b) && static_eval - REVERSE_FUTILITY_MARGIN * depth >= b {
+        ctx.cutoff(ply, Cutoff::REVERSEFUTILITY);
+        return Some(static_eval);
+    }
+
+    // Null move - if passing the turn still fails high, a real move would too.
+    // Only where the game says passing is a fair test, and not twice in a row.
+    if prune && ctx.options.null_move_pruning && depth >= NULL_MOVE_MIN_DEPTH && static_eval >= b
+        && game.can_null_move() && !game.last_move().is_some_and(|m| m.is_null()) {
+        let r = NULL_MOVE_REDUCTION + depth / 6;
+        game.do_null_move();
+        let null_score = -alpha_beta(game, depth - 1 - r, -b, -b + 1, ctx)?;
+        game.undo_null_move();
+        // A mate found after passing isn't proven.
+        if null_score >= b {
+            ctx.cutoff(ply, Cutoff::NULLMOVE);
+            return Some(if is_mate_score::<G>(null_score) { b } else { null_score });
         }
-    } else {
-        score = Chess::MAX_INFINITY;
-        for m in possible_moves {
-            let to = m.eaten_loc;
-            game.do_move(m);
-            score = min(score, min_max(game, depth - 1, to));
+    }
+
+    // Futility - quiet moves that would need to gain more than the margin can't raise a.
+    let futile = prune && ctx.options.futility_pruning && depth <= 2 && !is_mate_score::<G>(a) && static_eval + FUTILITY_MARGIN[depth as usize] <= a;
+
+    let mut possible_moves = game.possible_moves();
+    if possible_moves.is_empty() {
+        ctx.cutoff(ply, Cutoff::NOMOVES);
+        return Some(if game.is_lost() { mated_score::<G>(ply) } else { draw_score(ply, ctx) });
+    }
+    let countermove = ctx.ordering.countermove(game.last_move());
+    let ordering = &ctx.ordering;
+    possible_moves.sort_by_cached_key(|m| -ordering.score(game, ply, m, tt_move, countermove));
+
+    let orig_a = a;
+    let mut score = mated_score::<G>(ply);
+    let mut best_move = None;
+    // Quiet moves that didn't cause a cutoff, their history is lowered when a later one does.
+    let mut quiets_tried = vec![];
+
+    for (i, m) in possible_moves.into_iter().enumerate() {
+        let quiet = m.is_quiet();
+        game.do_move(m);
+        let gives_check = quiet && game.is_in_check();
+
+        if futile && i > 0 && quiet && !gives_check {
             game.undo_move();
+            score = max(score, static_eval + FUTILITY_MARGIN[depth as usize]);
+            continue;
+        }
+
+        ctx.pv[ply + 1].clear();
+        let move_score = if i == 0 {
+            -alpha_beta(game, depth - 1, -b, -a, ctx)?
+        } else {
+            // Late quiet moves are probably bad - search them shallower first.
+            let reduction = if ctx.options.late_move_reductions && depth >= LMR_MIN_DEPTH && i >= LMR_MIN_MOVE && quiet && !in_check && !gives_check {
+                late_move_reduction(depth, i)
+            } else {
+                0
+            };
+
+            // Expect the move to be worse - prove it with a zero window, and re-search if it isn't.
+            let mut move_score = -alpha_beta(game, depth - 1 - reduction, -a - 1, -a, ctx)?;
+            if reduction > 0 && move_score > a {
+                move_score = -alpha_beta(game, depth - 1, -a - 1, -a, ctx)?;
+            }
+            if move_score > a && move_score < b {
+                move_score = -alpha_beta(game, depth - 1, -b, -a, ctx)?;
+            }
+            move_score
+        };
+        let m_ = game.undo_move();
+
+        if move_score > score {
+            score = move_score;
+            best_move = Some(m_.hash());
+            if score > a {
+                a = score;
+                ctx.update_pv(ply, m_.clone());
+            }
+            if score >= b {
+                if quiet {
+                    ctx.ordering.update_quiet_cutoff(game, ply, depth, &m_, &quiets_tried);
+                }
+                break;
+            }
+        }
+        if quiet {
+            quiets_tried.push(m_);
         }
     }
-    return score;
+
+    if score >= b {
+        ctx.cutoff(ply, Cutoff::BETA);
+    }
+    ctx.tt.store(key, depth, score_to_tt::<G>(score, ply), bound_for(score, orig_a, b), best_move);
+    return Some(score);
 }
 
-pub fn alpha_beta(game: &mut Chess, depth: i32, mut a: <Chess as Scored>::ScoreType, mut b: <Chess as Scored>::ScoreType, last_to: u64, killer_move_cache: &mut Caches, call_count: &mut i32, max_timestamp_ms: u128) -> Option<<Chess as Scored>::ScoreType>
+// Searches captures and promotions until the position is quiet, so it isn't evaluated in the middle of an exchange.
+pub fn quiescence<G: Searchable>(game: &mut G, mut a: i32, b: i32, ctx: &mut SearchContext<G>) -> Option<i32>
 {
-    if get_time() >= max_timestamp_ms {
+    if ctx.should_stop() {
         return None;
     }
 
+    ctx.call_count += 1;
+    ctx.quiescence_count += 1;
+    let ply = game.get_game_len() - ctx.root_len;
+    ctx.enter_node(ply);
+
     let mut possible_moves = game.possible_moves();
-    *call_count += 1;
+    let evasions = ctx.options.quiescence_check_evasions && game.is_in_check();
+    let mut score;
+    let mut stand_pat = 0;
 
-    if depth <= 0 {
-        // Quiescence.
-        possible_moves.retain(|m| m.eaten_loc != 0 && m.eaten_loc == last_to);
-        if possible_moves.len() == 0 {
-            return Some(game.get_score());
-        }
+    if evasions {
+        // In check standing pat isn't an option, every evasion is searched.
+        score = mated_score::<G>(ply);
     } else {
-        // Only sort if depth is high enough to be worth it - doesn't help
-        let at_depth = killer_move_cache.entry(game.get_game_len()).or_insert(HashMap::with_hasher(A {}));
-        possible_moves.sort_by_cached_key(|m| move_ordering(m, at_depth));
+        // Stand pat - the player to move doesn't have to capture.
+        stand_pat = evaluate::<G>(game);
+        if stand_pat >= b {
+            return Some(stand_pat);
+        }
+        a = max(a, stand_pat);
+        score = stand_pat;
+        possible_moves.retain(|m| m.is_tactical());
     }
 
-    let mut score;
+    possible_moves.sort_by_cached_key(|m| -m.mvv_lva());
 
-    if game.current_player() == PLAYER1 {
-        score = Chess::MIN_INFINITY + (game.get_game_len() * 100) as <Chess as Scored>::ScoreType;
-        for m in possible_moves {
-            let to = m.eaten_loc;
-            game.do_move(m);
-            let move_score = if depth > 1 || to != 0 {alpha_beta(game, depth - 1, a, b, to, killer_move_cache, call_count, max_timestamp_ms)?} else { game.get_score() };
-            score = max(score, move_score);
-            let m_ = game.undo_move();
-            // Specifying >= here would let me look at less positions. But I can no longer trust an equal score. If the scores are equal I need to take the first.
-            // But I want the engine to take a random move among the best - so I need to be able to trust ties.
-            if score > b {
-                if depth >= 0 && m_.eaten_loc == 0 {
-                    *killer_move_cache.entry(game.get_game_len()).or_insert(HashMap::with_hasher(A {})).entry(m_.hash()).or_insert(0) += 1;
-                }
-                break;
+    for m in possible_moves {
+        if !evasions {
+            // Delta pruning - even winning the material for free doesn't get to a.
+            if ctx.options.delta_pruning && stand_pat + m.material_gain() + DELTA_MARGIN <= a {
+                continue;
+            }
+            // Losing captures.
+            if m.is_capture() && game.see(&m) < 0 {
+                continue;
             }
-            a = max(a, score);
         }
-    } else {
-        score = Chess::MAX_INFINITY - (game.get_game_len() * 100) as <Chess as Scored>::ScoreType;
-        for m in possible_moves {
-            let to = m.eaten_loc;
-            game.do_move(m);
-            let move_score = if depth > 1 || to != 0 {alpha_beta(game, depth - 1, a, b, to, killer_move_cache, call_count, max_timestamp_ms)?} else { game.get_score() };
-            score = min(score, move_score);
-            let m_ = game.undo_move();
-            if score < a {
-                if depth >= 0 && m_.eaten_loc == 0 {
-                    *killer_move_cache.entry(game.get_game_len()).or_insert(HashMap::with_hasher(A {})).entry(m_.hash()).or_insert(0) += 1;
-                }
+
+        game.do_move(m);
+        let move_score = -quiescence(game, -b, -a, ctx)?;
+        game.undo_move();
+
+        if move_score > score {
+            score = move_score;
+            if score > a {
+                a = score;
+            }
+            if score >= b {
                 break;
             }
-            b = min(b, score);
         }
     }
+
     return Some(score);
 }
 
+// Reduction for the i-th move at depth, grows slowly with both. Leaves at least one ply to search.
+fn late_move_reduction(depth: i32, i: usize) -> i32 {
+    let r = ((depth as f64).ln() * (i as f64).ln() / 2.0) as i32;
+    r.clamp(1, max(depth - 2, 1))
+}
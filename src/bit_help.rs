@@ -5,6 +5,8 @@ use std::iter::{Copied, Chain};
 pub enum Dir {North, South, East, West, NorthEast, NorthWest, SouthEast, SouthWest}
 
 impl Dir {
+    // Used by the table generation in build.rs.
+    #[allow(dead_code)]
     pub fn mv(&self, place: u64) -> Option<u64> {
         let (x, y) = place_to_coord(place);
         let (dx, dy) = match *self {
@@ -113,6 +115,8 @@ pub fn place_to_coord(place: u64) -> (i32, i32) {
 }
 
 
+// Used by the table generation in build.rs.
+#[allow(dead_code)]
 pub fn ray(index: usize, dir: Dir) -> u64 {
     _ray(index, dir, 0)
 }
@@ -148,6 +152,7 @@ fn __ray(mut start: u64, cnt: i32, func: fn(u64) -> u64) -> u64 {
     ray
 }
 
+#[allow(dead_code)]
 pub fn ray_until_blocker(index: usize, blockers: u64, dir: Dir) -> u64 {
     // not including index, including blocker.
     let mut res = ray(index, dir);
@@ -1,17 +1,19 @@
+use std::cell::Cell;
 use std::cmp::{max, min};
 use std::convert::TryFrom;
 use std::fmt::{Display, Formatter};
 use std::hash::{Hash, Hasher};
-use crate::two_player_game::{Game, Player, GameState, Scored};
-use crate::bit_help::{index_to_place, place_to_coord, coord_to_index, Dir, index, iter_index, iter_place, ray_until_blocker};
+use crate::two_player_game::{Game, Player, GameState, Scored, SearchMove, Searchable};
+use crate::bit_help::{index_to_place, place_to_coord, coord_to_index, index, iter_index, iter_place, ray_until_blocker};
 use crate::two_player_game::Player::{PLAYER1, PLAYER2};
 use crate::chess_impl::PieceType::{PAWN, KNIGHT, BISHOP, QUEEN, ROOK, KING};
 use std::iter::Copied;
 use std::slice::Iter;
 use crate::move_generation::MoveTables;
 use crate::print_u64;
-use crate::two_player_game::GameState::PLAYING;
-use crate::move_generation::MOVE_TABLE;
+use crate::two_player_game::GameState::{PLAYING, PLAYER1WIN, PLAYER2WIN, TIE};
+use crate::zobrist::ZOBRIST;
+use crate::move_generation::{AttackInfo, MOVE_TABLE, pawn_attacks, pawn_pushes, pawn_double_pushes, pawn_attacks_east, pawn_attacks_west, pawn_shift_back, PAWN_EAST_SHIFT, PAWN_WEST_SHIFT, PROMOTION_ROWS};
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum PieceType { PAWN = 0, KNIGHT = 1, BISHOP = 2, ROOK = 3, QUEEN = 4, KING = 5 }
@@ -31,15 +33,6 @@ impl TryFrom<usize> for PieceType {
     }
 }
 
-impl Player {
-    pub fn dir(&self, dir: Dir) -> Dir {
-        match *self {
-            PLAYER2 => dir,
-            PLAYER1 => dir.flip()
-        }
-    }
-}
-
 static KING_PLACES: [u64; 2] = [1 << 3, 1 << (63 - 4)];
 static KINGSIDE_ROOKS: [u64; 2] = [1, 1 << (63 - 7)];
 static QUEENSIDE_ROOKS: [u64; 2] = [1 << 7, 1 << 63];
@@ -106,6 +99,40 @@ pub struct Move {
 }
 
 impl Move {
+    // Passing the turn, see Chess::do_null_move.
+    fn null() -> Move {
+        Move { from: 0, to: 0, start_type: KING, end_type: KING, eaten_type: KING, eaten_loc: 0 }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.from == 0
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.eaten_loc != 0
+    }
+
+    pub fn is_promotion(&self) -> bool {
+        self.start_type != self.end_type
+    }
+
+    // Neither a capture nor a promotion.
+    pub fn is_quiet(&self) -> bool {
+        !self.is_capture() && !self.is_promotion()
+    }
+
+    // What the move wins at most - the captured piece and the promotion.
+    pub fn material_gain(&self) -> i32 {
+        let captured = if self.is_capture() { SEE_VALUE[self.eaten_type as usize] } else { 0 };
+        let promoted = if self.is_promotion() { SEE_VALUE[self.end_type as usize] - SEE_VALUE[PAWN as usize] } else { 0 };
+        captured + promoted
+    }
+
+    // Most valuable victim, least valuable attacker. Higher is searched first.
+    pub fn mvv_lva(&self) -> i32 {
+        self.material_gain() * 8 - self.start_type as i32
+    }
+
     pub fn hash(&self) -> u64 {
         self.from.trailing_zeros() as u64 |
             (self.to.trailing_zeros() as u64) << 8 |
@@ -144,11 +171,46 @@ impl Display for Move {
     }
 }
 
+#[derive(Clone)]
+struct HistoryEntry {
+    play: Move,
+    castle_memory: u64,
+    en_passant_square: u64,
+    // The attack info of the position before the move, so undo doesn't recompute it.
+    attack_info: Option<AttackInfo>,
+    hash: u64,
+    halfmove_clock: u32,
+}
+
 #[derive(Clone)]
 pub struct Chess {
     current_player: Player,
     board: BoardState,
-    history: Vec<(Move, u64, u64)>,
+    history: Vec<HistoryEntry>,
+
+    // Computed at most once per position, see attack_info.
+    attack_info: Cell<Option<AttackInfo>>,
+
+    // Zobrist hash of the position, updated incrementally.
+    hash: u64,
+
+    // Plies since the last capture or pawn move, for the fifty move rule.
+    halfmove_clock: u32,
+}
+
+// h1 is a light square.
+const LIGHT_SQUARES: u64 = 0xAA55_AA55_AA55_AA55;
+
+fn piece_hash(player: Player, piece_type: PieceType, place: u64) -> u64 {
+    if place == 0 { 0 } else { ZOBRIST.pieces[player as usize][piece_type as usize][index(place)] }
+}
+
+fn castle_hash(castle_memory: u64) -> u64 {
+    iter_index(castle_memory).fold(0, |hash, i| hash ^ ZOBRIST.castle[i])
+}
+
+fn en_passant_hash(en_passant_square: u64) -> u64 {
+    if en_passant_square == 0 { 0 } else { ZOBRIST.en_passant[index(en_passant_square) % 8] }
 }
 
 impl Chess {
@@ -156,6 +218,7 @@ impl Chess {
     pub fn setup_fen_string(&mut self, fen: &str) {
 
         self.history.clear();
+        self.attack_info.set(None);
 
         self.board.piece_state = [[0; 6]; 2];
 
@@ -218,6 +281,10 @@ impl Chess {
         } else {
             self.board.en_passant_square = 0;
         }
+
+        self.halfmove_clock = parts.get(4).and_then(|p| p.parse().ok()).unwrap_or(0);
+
+        self.hash = self.compute_hash();
     }
 
     pub fn get_fen_string(&self) -> String {
@@ -283,8 +350,9 @@ impl Chess {
             *res += "-";
         }
 
-        // bogus half-turn counter
-        *res += " 0 ";
+        *res += " ";
+        *res += &self.halfmove_clock.to_string();
+        *res += " ";
 
         // fullturn counter
         *res += &(self.history.len() / 2).to_string();
@@ -292,10 +360,157 @@ impl Chess {
         res.to_string()
     }
 
+    // Attack maps, checkers and pins of the current position. Cached until the position changes.
+    pub fn attack_info(&self) -> AttackInfo {
+        if let Some(info) = self.attack_info.get() {
+            return info;
+        }
+        let info = MOVE_TABLE.get_attack_info(&self.board, self.current_player);
+        self.attack_info.set(Some(info));
+        info
+    }
+
+    // Pieces of both players attacking the square at index, with the given occupancy.
+    fn attackers_to(&self, index: usize, occ: u64) -> u64 {
+        let board = &self.board;
+        let place = index_to_place(index);
+        let both = |piece_type| board.get(PLAYER1, piece_type) | board.get(PLAYER2, piece_type);
+        let queens = both(QUEEN);
+
+        (MOVE_TABLE.get_knight_moves(index) & both(KNIGHT))
+            | (MOVE_TABLE.get_king_moves(index) & both(KING))
+            | (MOVE_TABLE.get_bishop_moves(index, occ) & (both(BISHOP) | queens))
+            | (MOVE_TABLE.get_rook_moves(index, occ) & (both(ROOK) | queens))
+            | (pawn_attacks(PLAYER2, place) & board.get(PLAYER1, PAWN))
+            | (pawn_attacks(PLAYER1, place) & board.get(PLAYER2, PAWN))
+    }
+
+    // Static exchange evaluation - the material the player to move wins with m, if both players keep
+    // recapturing on its square with their least valuable piece. Pins are ignored.
+    pub fn see(&self, m: &Move) -> i32 {
+        // Nothing of the opponent sees the square, not even through the squares the move empties.
+        let enemy_attacks = self.attack_info().attacks[self.current_player.other() as usize];
+        if enemy_attacks & (m.to | m.from | m.eaten_loc) == 0 {
+            return m.material_gain();
+        }
+
+        let to = index(m.to);
+        let mut occ = self.board.all_occupancy() & !m.from & !m.eaten_loc | m.to;
+        let mut gain = [0; 32];
+        gain[0] = m.material_gain();
+        let mut on_square = m.end_type;
+        let mut player = self.current_player.other();
+        let mut d = 0;
+
+        loop {
+            let attackers = self.attackers_to(to, occ) & occ & self.board.occupancy(player);
+            let least_valuable = PieceType::all()
+                .map(|piece_type| (piece_type, attackers & self.board.get(player, piece_type)))
+                .find(|(_, places)| *places != 0);
+            let (piece_type, places) = match least_valuable {
+                Some(x) => x,
+                None => break
+            };
+
+            d += 1;
+            gain[d] = SEE_VALUE[on_square as usize] - gain[d - 1];
+            occ &= !(places & places.wrapping_neg());
+            on_square = piece_type;
+            player = player.other();
+        }
+
+        // Either player may stop capturing when it would lose material.
+        while d > 0 {
+            gain[d - 1] = -max(-gain[d - 1], gain[d]);
+            d -= 1;
+        }
+        gain[0]
+    }
+
+    pub fn is_in_check(&self) -> bool {
+        self.attack_info().checkers != 0
+    }
+
+    pub fn get_hash(&self) -> u64 {
+        self.hash
+    }
+
+    // Hash the position from scratch. do_move keeps the hash up to date incrementally.
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = 0;
+        for player in [PLAYER1, PLAYER2].iter().copied() {
+            for piece_type in PieceType::all() {
+                for place in iter_place(self.board.get(player, piece_type)) {
+                    hash ^= piece_hash(player, piece_type, place);
+                }
+            }
+        }
+        hash ^= castle_hash(self.board.castle_memory);
+        hash ^= en_passant_hash(self.board.en_passant_square);
+        if self.current_player == PLAYER2 {
+            hash ^= ZOBRIST.player2_to_move;
+        }
+        hash
+    }
+
     pub fn get_game_len(&self) -> usize {
         self.history.len()
     }
 
+    pub fn last_move(&self) -> Option<&Move> {
+        self.history.last().map(|e| &e.play)
+    }
+
+    // Pass the turn to the other player. Moves no pieces, so do_move handles it as is.
+    // Only for the search - the position after it is illegal when in check.
+    pub fn do_null_move(&mut self) {
+        self.do_move(Move::null());
+    }
+
+    pub fn undo_null_move(&mut self) {
+        let play = self.undo_move();
+        debug_assert!(play.is_null());
+    }
+
+    // Earlier occurrences of the current position. Only positions since the last capture or pawn move can repeat.
+    pub fn repetitions(&self) -> usize {
+        self.history.iter().rev()
+            .take(self.halfmove_clock as usize)
+            .take_while(|e| !e.play.is_null())
+            .skip(1)
+            .step_by(2)
+            .filter(|e| e.hash == self.hash)
+            .count()
+    }
+
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+
+    // Neither player can mate - kings with at most a single minor piece, or only bishops on one color.
+    pub fn has_insufficient_material(&self) -> bool {
+        let board = &self.board;
+        let both = |piece_type| board.get(PLAYER1, piece_type) | board.get(PLAYER2, piece_type);
+        if both(PAWN) | both(ROOK) | both(QUEEN) != 0 {
+            return false;
+        }
+        let knights = both(KNIGHT);
+        let bishops = both(BISHOP);
+        (knights | bishops).count_ones() <= 1
+            || knights == 0 && (bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0)
+    }
+
+    // Drawn by the rules, other than stalemate. In the search a single repetition is enough.
+    pub fn is_draw(&self) -> bool {
+        self.is_fifty_move_draw() || self.has_insufficient_material() || self.repetitions() >= 1
+    }
+
+    // Whether the player to move has pieces besides pawns and the king - without them zugzwang is likely.
+    pub fn has_non_pawn_material(&self) -> bool {
+        let player = self.current_player;
+        self.board.occupancy(player) & !self.board.get(player, PAWN) & !self.board.get(player, KING) != 0
+    }
+
     pub fn all_pieces(&self) -> Vec<(i32, i32, Player, PieceType)> {
         let mut res = Vec::new();
         for x in 0..8 {
@@ -320,10 +535,10 @@ impl Chess {
         (index_to_place(from_index), index_to_place(to_index))
     }
 
-    fn add_king_moves(&self, possible_moves: &mut Vec<Move>) -> (u64, u64, u64) {
-        // Return (king_danger_squares, checkers, push_mask)
+    fn add_king_moves(&self, possible_moves: &mut Vec<Move>, info: &AttackInfo) -> u64 {
+        // Return push_mask
         let king_place = self.board.get(self.current_player, KING);
-        let king_danger_squares = MOVE_TABLE.get_king_danger_squares(&self.board, self.current_player);
+        let king_danger_squares = info.king_danger;
         let king_moves = MOVE_TABLE.get_king_moves(index(king_place))
             & !self.board.occupancy(self.current_player)
             & !king_danger_squares;
@@ -331,17 +546,17 @@ impl Chess {
 
         self.add_moves(possible_moves, king_place, king_moves, KING, self.board.occupancy(self.current_player.other()));
 
-        let checkers = self.get_checkers();
+        let checkers = info.checkers;
         if checkers != 0 {
             if checkers.count_ones() > 1 {
-                return (king_danger_squares, checkers, 0);
+                return 0;
             }
             push_mask = MOVE_TABLE.get_ray(index(king_place), index(checkers))
         } else {
             self.add_castle_moves(possible_moves, checkers, king_danger_squares);
         }
 
-        (king_danger_squares, checkers, push_mask)
+        push_mask
     }
 
     fn add_castle_moves(&self, possible_moves: &mut Vec<Move>, checkers: u64, king_danger: u64) {
@@ -380,37 +595,22 @@ impl Chess {
         }
     }
 
-    fn add_pinned_moves(&self, possible_moves: &mut Vec<Move>, capture_mask: u64, push_mask: u64) -> u64 {
-        // Returns a board of pinned pieces.
+    fn add_pinned_moves(&self, possible_moves: &mut Vec<Move>, capture_mask: u64, push_mask: u64, pinners: u64) {
         let enemy = self.current_player.other();
         let my_occ = self.board.occupancy(self.current_player);
         let enemy_occ = self.board.occupancy(enemy);
         let occ = my_occ | enemy_occ;
         let king_index = index(self.board.get(self.current_player, KING));
 
-        let enemy_rooks = self.board.get(enemy, ROOK) | self.board.get(enemy, QUEEN);
-        let enemy_bishops = self.board.get(enemy, BISHOP) | self.board.get(enemy, QUEEN);
-
-        let mut pinned = 0_u64;
-        let mut pinners = 0_u64;
-        pinners |= MOVE_TABLE.get_rook_moves(king_index, enemy_occ) & enemy_rooks;
-        pinners |= MOVE_TABLE.get_bishop_moves(king_index, enemy_occ) & enemy_bishops;
-
         for i in iter_index(pinners) {
             let pin = MOVE_TABLE.get_ray(king_index, i) & my_occ;
-            if pin.count_ones() == 1 {
-                let pin_space = index_to_place(i) | MOVE_TABLE.get_ray(king_index, i);
-                let (_, piece_type) = self.board.type_at(pin).unwrap();
-                let moves = MOVE_TABLE.get_moves(index(pin), self.current_player, piece_type, occ)
-                    & pin_space & (capture_mask | push_mask) & !my_occ;
-
+            let pin_space = index_to_place(i) | MOVE_TABLE.get_ray(king_index, i);
+            let (_, piece_type) = self.board.type_at(pin).unwrap();
+            let moves = MOVE_TABLE.get_moves(index(pin), self.current_player, piece_type, occ)
+                & pin_space & (capture_mask | push_mask) & !my_occ;
 
-                self.add_moves(possible_moves, pin, moves, piece_type, enemy_occ);
-                pinned |= pin;
-            }
+            self.add_moves(possible_moves, pin, moves, piece_type, enemy_occ);
         }
-
-        pinned
     }
 
     fn add_en_passant_captures(&self, possible_moves: &mut Vec<Move>, capture_mask: u64, push_mask: u64, pinned: u64) {
@@ -447,20 +647,23 @@ impl Chess {
         }
     }
 
-    fn get_checkers(&self) -> u64 {
-        let mut checkers = 0;
-
-        let king_index = index(self.board.get(self.current_player, KING));
-        let enemy = self.current_player.other();
-        let occ = self.board.all_occupancy();
-
-        checkers |= MOVE_TABLE.get_rook_moves(king_index, occ) & (self.board.get(enemy, QUEEN) | self.board.get(enemy, ROOK));
-        checkers |= MOVE_TABLE.get_bishop_moves(king_index, occ) & (self.board.get(enemy, QUEEN) | self.board.get(enemy, BISHOP));
-        checkers |= MOVE_TABLE.get_knight_moves(king_index) & self.board.get(enemy, KNIGHT);
-
-        checkers |= MOVE_TABLE.get_pawn_captures(self.current_player, king_index, self.board.get(enemy, PAWN));
-
-        checkers
+    fn add_pawn_moves(&self, possible_moves: &mut Vec<Move>, pawns: u64, mask: u64, enemy_occ: u64) {
+        // Generates the moves of all the given pawns at once. Does not include en passant.
+        let player = self.current_player;
+        let empty = !self.board.all_occupancy();
+
+        let targets = [
+            (pawn_pushes(player, pawns, empty), 8),
+            (pawn_double_pushes(player, pawns, empty), 16),
+            (pawn_attacks_east(player, pawns) & enemy_occ, PAWN_EAST_SHIFT[player as usize]),
+            (pawn_attacks_west(player, pawns) & enemy_occ, PAWN_WEST_SHIFT[player as usize]),
+        ];
+
+        for (to_board, shift) in targets.iter().copied() {
+            for to in iter_place(to_board & mask) {
+                self.add_moves(possible_moves, pawn_shift_back(player, to, shift), to, PAWN, enemy_occ);
+            }
+        }
     }
 
     fn add_moves(&self, possible_moves: &mut Vec<Move>, from: u64, to_options: u64, piece_type: PieceType, enemy_occ: u64) {
@@ -470,7 +673,7 @@ impl Chess {
             } else {
                 (0, PAWN)
             };
-            if piece_type == PAWN && (index(to) / 8) % 7 == 0 {
+            if piece_type == PAWN && to & PROMOTION_ROWS[self.current_player as usize] != 0 {
                 for end_type in [QUEEN, ROOK, BISHOP, KNIGHT].iter().cloned() {
                     possible_moves.push(Move {
                         from,
@@ -508,6 +711,9 @@ impl Game for Chess {
                 en_passant_square: 0,
             },
             history: vec![],
+            attack_info: Cell::new(None),
+            hash: 0,
+            halfmove_clock: 0,
         };
         chess.setup_new_game();
         chess
@@ -524,20 +730,28 @@ impl Game for Chess {
     fn possible_moves(&self) -> Vec<Self::MoveType> {
         let mut possible_moves = Vec::with_capacity(50);
 
-        let (king_danger, checkers, push_mask) = self.add_king_moves(&mut possible_moves);
+        let info = self.attack_info();
+        let checkers = info.checkers;
+        let pinned = info.pinned;
+
+        let push_mask = self.add_king_moves(&mut possible_moves, &info);
 
         if checkers.count_ones() > 1 {
             return possible_moves;
         }
 
-        let pinned = self.add_pinned_moves(&mut possible_moves, checkers, push_mask);
+        self.add_pinned_moves(&mut possible_moves, checkers, push_mask, info.pinners);
 
         self.add_en_passant_captures(&mut possible_moves, checkers, push_mask, pinned);
 
         let my_occ = self.board.occupancy(self.current_player);
-        let can_move = my_occ & !pinned & !self.board.get(self.current_player, KING);
         let blockers = self.board.all_occupancy();
         let enemy_occ = blockers & !my_occ;
+
+        let pawns = self.board.get(self.current_player, PAWN) & !pinned;
+        self.add_pawn_moves(&mut possible_moves, pawns, checkers | push_mask, enemy_occ);
+
+        let can_move = my_occ & !pinned & !self.board.get(self.current_player, KING) & !pawns;
         for p in iter_place(can_move) {
             let (_, piece_type) = self.board.type_at(p).unwrap();
             let moves = MOVE_TABLE.get_moves(index(p), self.current_player, piece_type, blockers)
@@ -580,8 +794,15 @@ impl Game for Chess {
     }
 
     fn do_move(&mut self, play: Self::MoveType) {
+        let irreversible = play.start_type == PAWN || play.eaten_loc != 0;
         let prev_castle = self.board.castle_memory;
         let prev_en_passant = self.board.en_passant_square;
+        let prev_hash = self.hash;
+
+        let mut hash = self.hash ^ castle_hash(prev_castle) ^ en_passant_hash(prev_en_passant) ^ ZOBRIST.player2_to_move;
+        hash ^= piece_hash(self.current_player, play.start_type, play.from);
+        hash ^= piece_hash(self.current_player, play.end_type, play.to);
+        hash ^= piece_hash(self.current_player.other(), play.eaten_type, play.eaten_loc);
 
         *self.board.get_mut(self.current_player, play.start_type) &= !play.from;
         *self.board.get_mut(self.current_player, play.end_type) |= play.to;
@@ -595,6 +816,7 @@ impl Game for Chess {
             if (from_x as i32 - to_x as i32).abs() == 2 {
                 let (from_index, to_index) = Chess::castle_rook_move(play.to);
                 self.board.move_piece(self.current_player, PieceType::ROOK, from_index, to_index);
+                hash ^= piece_hash(self.current_player, ROOK, from_index) ^ piece_hash(self.current_player, ROOK, to_index);
             }
         }
 
@@ -610,12 +832,26 @@ impl Game for Chess {
 
         self.board.castle_memory = self.board.castle_memory & !play.from;
         self.current_player = self.current_player.other();
-
-        self.history.push((play, prev_castle, prev_en_passant));
+        self.hash = hash ^ castle_hash(self.board.castle_memory) ^ en_passant_hash(self.board.en_passant_square);
+
+        self.history.push(HistoryEntry {
+            play,
+            castle_memory: prev_castle,
+            en_passant_square: prev_en_passant,
+            attack_info: self.attack_info.take(),
+            hash: prev_hash,
+            halfmove_clock: self.halfmove_clock,
+        });
+
+        if irreversible {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
     }
 
     fn undo_move(&mut self) -> Self::MoveType {
-        let (play, castle_memory, en_passant_square) = self.history.pop().unwrap();
+        let HistoryEntry { play, castle_memory, en_passant_square, attack_info, hash, halfmove_clock } = self.history.pop().unwrap();
 
         self.current_player = self.current_player.other();
 
@@ -636,13 +872,23 @@ impl Game for Chess {
 
         self.board.castle_memory = castle_memory;
         self.board.en_passant_square = en_passant_square;
-
+        self.attack_info.set(attack_info);
+        self.hash = hash;
+        self.halfmove_clock = halfmove_clock;
 
         play
     }
 
     fn game_state(&self) -> GameState {
-        // todo: Implement or some shit.
+        if self.possible_moves().is_empty() {
+            if !self.is_in_check() {
+                return TIE;
+            }
+            return if self.current_player == PLAYER1 { PLAYER2WIN } else { PLAYER1WIN };
+        }
+        if self.repetitions() >= 2 || self.is_fifty_move_draw() || self.has_insufficient_material() {
+            return TIE;
+        }
         return PLAYING;
     }
 
@@ -671,10 +917,11 @@ impl Game for Chess {
 impl Scored for Chess {
     type ScoreType = i32;
     const MAX_INFINITY: Self::ScoreType = i32::MAX;
-    const MIN_INFINITY: Self::ScoreType = i32::MIN;
-    const MAX_SCORE: Self::ScoreType = 1000;
+    // Not i32::MIN, so it can be negated.
+    const MIN_INFINITY: Self::ScoreType = -i32::MAX;
+    const MAX_SCORE: Self::ScoreType = 100_000;
     const NEUTRAL_SCORE: Self::ScoreType = 0;
-    const MIN_SCORE: Self::ScoreType = -1000;
+    const MIN_SCORE: Self::ScoreType = -100_000;
 
     fn get_score(&self) -> Self::ScoreType {
         let mut game_phase = 0;
@@ -695,6 +942,15 @@ impl Scored for Chess {
             }
         }
 
+        /* squares controlled */
+        let attacks = self.attack_info().attacks;
+        for &player in [PLAYER1, PLAYER2].iter() {
+            let mult = player as i32 * -2 + 1;
+            let controlled = attacks[player as usize].count_ones() as i32;
+            mg_score += mult * controlled * MG_CONTROL;
+            eg_score += mult * controlled * EG_CONTROL;
+        }
+
         /* tapered eval */
         let mg_phase = min(game_phase, 24);
         let eg_phase = 24 - mg_phase;
@@ -703,6 +959,42 @@ impl Scored for Chess {
 
 }
 
+impl SearchMove for Move {
+    fn hash(&self) -> u64 { Move::hash(self) }
+    fn is_quiet(&self) -> bool { Move::is_quiet(self) }
+    fn is_capture(&self) -> bool { Move::is_capture(self) }
+    // Underpromotions are never better in quiet positions.
+    fn is_tactical(&self) -> bool { !self.is_quiet() && (!self.is_promotion() || self.end_type == QUEEN) }
+    fn is_null(&self) -> bool { Move::is_null(self) }
+    fn material_gain(&self) -> i32 { Move::material_gain(self) }
+    fn mvv_lva(&self) -> i32 { Move::mvv_lva(self) }
+    fn squares(&self) -> (usize, usize) {
+        (self.from.trailing_zeros() as usize % 64, self.to.trailing_zeros() as usize % 64)
+    }
+}
+
+impl Searchable for Chess {
+    fn get_hash(&self) -> u64 { Chess::get_hash(self) }
+    fn get_game_len(&self) -> usize { Chess::get_game_len(self) }
+    fn is_in_check(&self) -> bool { Chess::is_in_check(self) }
+    // Checkmate, otherwise stalemate.
+    fn is_lost(&self) -> bool { Chess::is_in_check(self) }
+    fn is_draw(&self) -> bool { Chess::is_draw(self) }
+    fn last_move(&self) -> Option<&Move> { Chess::last_move(self) }
+    // Not with only pawns left, where passing may be the best move.
+    fn can_null_move(&self) -> bool { self.has_non_pawn_material() }
+    fn do_null_move(&mut self) { Chess::do_null_move(self) }
+    fn undo_null_move(&mut self) { Chess::undo_null_move(self) }
+    fn see(&self, m: &Move) -> i32 { Chess::see(self, m) }
+}
+
+
+// Piece values for exchanges, the king is worth more than anything it can win.
+pub static SEE_VALUE: [i32; 6] = [100, 320, 330, 500, 900, 20000];
+
+// Per attacked square.
+static MG_CONTROL: i32 = 2;
+static EG_CONTROL: i32 = 1;
 
 static MG_VALUE: [i32; 6] = [ 82, 337, 365, 477, 1025,  0];
 static EG_VALUE: [i32; 6] = [ 94, 281, 297, 512,  936,  0];
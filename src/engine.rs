@@ -0,0 +1,53 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::alpha_beta::{get_next_move_with_tt, MoveResult, SearchOptions};
+use crate::move_ordering::MoveOrdering;
+use crate::search_observer::SearchObserver;
+use crate::two_player_game::Searchable;
+use crate::search_limits::SearchLimits;
+use crate::transposition::TranspositionTable;
+
+// Searches the moves of a game, keeping the transposition table and the move ordering tables between them.
+// Any Searchable game, one at a time.
+pub struct Engine {
+    tt: TranspositionTable,
+    // One per search thread.
+    orderings: Vec<MoveOrdering>,
+    pub options: SearchOptions,
+    stop: Arc<AtomicBool>,
+}
+
+impl Engine {
+    pub fn new(hash_mb: usize, options: SearchOptions) -> Engine {
+        Engine {
+            tt: TranspositionTable::new(hash_mb),
+            orderings: vec![],
+            options,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Forget the previous game.
+    #[allow(dead_code)]
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+        self.orderings.clear();
+    }
+
+    // Set it to stop the current search, from another thread.
+    #[allow(dead_code)]
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    pub fn search<G: Searchable>(&mut self, game: &mut G, limits: &SearchLimits<G::MoveType>, observer: &mut dyn SearchObserver<G>) -> MoveResult<G> {
+        self.stop.store(false, Ordering::Relaxed);
+        self.orderings.resize_with(self.options.threads.max(1), MoveOrdering::new);
+        get_next_move_with_tt(game, limits, &mut self.tt, &mut self.orderings, self.options, self.stop.clone(), observer)
+    }
+
+    #[cfg(test)]
+    pub fn hashfull(&self) -> usize {
+        self.tt.hashfull()
+    }
+}
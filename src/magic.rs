@@ -0,0 +1,28 @@
+// Fancy magic bitboards.
+// Each square gets a magic number such that multiplying any subset of the square's blocker mask by it
+// gives a unique key in the top bits (or a key shared only with subsets that have the same moves).
+// The attack boards of all squares are packed one after the other into a single shared table,
+// and each square stores its offset into it - so a square only takes as much space as its mask needs.
+// The magics themselves are found at build time, see table_gen.rs.
+
+#[derive(Clone, Copy, Debug)]
+pub struct Magic {
+    // Relevant blockers for this square - not including board edge
+    pub mask: u64,
+    pub magic: u64,
+    pub shift: u32,
+    // Where this square's attack boards start in the shared table.
+    pub offset: usize,
+}
+
+impl Magic {
+    #[inline]
+    pub fn index(&self, blockers: u64) -> usize {
+        self.offset + self.key(blockers)
+    }
+
+    #[inline]
+    pub fn key(&self, blockers: u64) -> usize {
+        ((blockers & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
@@ -1,23 +1,39 @@
-use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use text_io::read;
 
-use crate::two_player_game::{Game, GameState, Player, Scored};
-use crate::alpha_beta::{get_next_move, MoveResult, alpha_beta, A};
+use crate::two_player_game::{Game, GameState, Player};
+use crate::alpha_beta::{get_next_move, pv_string, score_string, MoveResult, SearchOptions, TieBreak};
+use crate::transposition::DEFAULT_HASH_MB;
+use crate::search_limits::SearchLimits;
+use crate::search_observer::StderrObserver;
+use crate::engine::Engine;
+use crate::mate_solver::{solve_mate, Proof};
+use crate::mcts::{score_evaluation, Mcts, MctsOptions, Playout};
 use crate::bit_help::{coord_to_index, Dir, index, index_to_place, place_to_coord, ray, ray_until_blocker};
 use crate::chess_impl::{Chess, Move};
 use crate::two_player_game::GameState::PLAYING;
-use crate::two_player_game::Player::PLAYER1;
-use crate::utils::get_time;
+use crate::two_player_game::Player::{PLAYER1, PLAYER2};
 
 mod two_player_game;
 mod alpha_beta;
 mod chess_impl;
 mod bit_help;
 mod move_generation;
+mod move_ordering;
+mod magic;
+#[cfg(test)]
+mod table_gen;
 mod tests;
-mod utils;
+mod zobrist;
+mod transposition;
+mod time_manager;
+mod search_limits;
+mod search_observer;
+mod engine;
+mod mcts;
+mod mate_solver;
+mod search_trace;
 
 
 fn print_u64(map: u64) {
@@ -35,9 +51,10 @@ fn print_u64(map: u64) {
 
 
 fn play_game_chess_com(game: &mut Chess, player: Player) -> GameState {
+    let mut engine = Engine::new(DEFAULT_HASH_MB, SearchOptions::default());
     loop {
         if game.current_player() == player {
-            let om = get_next_move(game, 8, u128::MAX);
+            let om = engine.search(game, &SearchLimits::depth(8), &mut StderrObserver);
             match om {
                 MoveResult { chess_move: Some(m), .. } => {
                     println!("{}", m);
@@ -83,7 +100,7 @@ fn output_possible_moves(game: &Chess) {
     }
 }
 
-fn input_move(game: &Chess) -> (Option<Move>, u128) {
+fn input_move(game: &Chess) -> (Option<Move>, SearchLimits<Move>) {
     let move_string: String = read!("{}__");
     let timeout_seconds: u128 = read!("{}\n");
     let _m: Option<Move> = game.possible_moves().into_iter().filter(|m| m.serialize() == move_string).nth(0);
@@ -94,12 +111,11 @@ fn input_move(game: &Chess) -> (Option<Move>, u128) {
         println!("ERROR");
     }
 
-    let max_timestamp_ms = get_time() + timeout_seconds * 1000;
-    eprintln!("Timeout seconds: {}, max_timestamp_ms: {}", timeout_seconds, max_timestamp_ms);
-    return (_m, max_timestamp_ms);
+    eprintln!("Timeout seconds: {}", timeout_seconds);
+    return (_m, SearchLimits::movetime(Duration::from_secs(timeout_seconds as u64)));
 }
 
-fn play_game_my_front(human_as: Player, depth: i32) {
+fn play_game_my_front(human_as: Player, depth: i32, options: SearchOptions) {
     /*
         game state
         if human_turn: possible_moves
@@ -108,7 +124,8 @@ fn play_game_my_front(human_as: Player, depth: i32) {
 
      */
     let mut game = Chess::new();
-    let mut max_timestamp_ms = 0;
+    let mut engine = Engine::new(DEFAULT_HASH_MB, options);
+    let mut limits = SearchLimits::default();
     let mut om;
 
     loop {
@@ -122,18 +139,17 @@ fn play_game_my_front(human_as: Player, depth: i32) {
             }
 
             let tup = input_move(&game);
-            om = tup.0; max_timestamp_ms = tup.1;
+            om = tup.0; limits = tup.1;
             while om.is_none() {
                 let tup = input_move(&game);
-                om = tup.0; max_timestamp_ms = tup.1;
+                om = tup.0; limits = tup.1;
             }
             game.do_move(om.unwrap());
         } else {
-            let om = get_next_move(&mut game, depth, max_timestamp_ms);
+            let om = engine.search(&mut game, &SearchLimits { time_control: limits.time_control, ..SearchLimits::depth(depth) }, &mut StderrObserver);
             match om {
                 MoveResult { chess_move: Some(m), ..} => {
                     game.do_move(m);
-                    eprintln!("Move from depth: {}", om.move_from_depth);
                 }
 
                 MoveResult { chess_move: None, .. } => {
@@ -145,13 +161,19 @@ fn play_game_my_front(human_as: Player, depth: i32) {
     }
 }
 
-fn play_self() {
+// Each player searches with its own options, to compare them.
+fn play_self(options: [SearchOptions; 2]) {
     let mut chess = Chess::new();
+    let mut engines = options.map(|o| Engine::new(DEFAULT_HASH_MB, o));
     let mut turns = 0;
     let start = Instant::now();
     loop {
         println!("At move: {}, took {:?}", turns, start.elapsed());
-        let m = get_next_move(&mut chess, 8, u128::MAX);
+        if chess.game_state() != PLAYING {
+            break;
+        }
+        let player = chess.current_player() as usize;
+        let m = engines[player].search(&mut chess, &SearchLimits::depth(8), &mut StderrObserver);
         match m {
             MoveResult { chess_move: None, .. } => { break; }
             MoveResult { chess_move: Some(m_), .. } => { println!("Found move: {}", m_); chess.do_move(m_); }
@@ -160,26 +182,87 @@ fn play_self() {
     }
 }
 
-fn print_state_at(fen: &str, move_str: &str, depth: i32) {
+// Alpha-beta against MCTS with eval playouts, each gets movetime per move.
+fn play_against_mcts(mcts_as: Player, movetime: Duration) -> GameState {
     let mut chess = Chess::new();
-    chess.setup_fen_string("8/1p6/1k6/4r1NP/1P6/2P2R2/1b3PP1/5K2 w - - 0 1");
-    let a = Chess::MIN_INFINITY;
-    let b = Chess::MAX_INFINITY;
-
-    println!("Alpha beta score: {}", alpha_beta(&mut chess, depth, a, b, 0, &mut HashMap::with_hasher(A {}), &mut 0, u128::MAX).unwrap() );
-    chess.do_move(chess.possible_moves().into_iter().filter(|m| m.to_string() == move_str).nth(0).unwrap());
-
-    for d in (0..depth).rev() {
-        // println!("Alpha beta score: {}", alpha_beta(&mut chess, d, a, b, 0, &mut HashMap::new()) );
-        let m = get_next_move(&mut chess, d, u128::MAX).chess_move.unwrap();
-        println!("Do Move: {}", m);
+    let mut engine = Engine::new(DEFAULT_HASH_MB, SearchOptions::default());
+    let mut mcts = Mcts::new(MctsOptions { playout: Playout::EVAL { plies: 4, evaluate: score_evaluation }, ..MctsOptions::default() });
+    let limits = SearchLimits::movetime(movetime);
+    while chess.game_state() == PLAYING {
+        let result = if chess.current_player() == mcts_as {
+            mcts.search(&mut chess, &limits)
+        } else {
+            engine.search(&mut chess, &limits, &mut StderrObserver)
+        };
+        let Some(m) = result.chess_move else { break };
+        println!("Found move: {}, Score: {}, Nodes: {}", m, result.score, result.nodes);
+        mcts.advance(&m);
         chess.do_move(m);
     }
+    chess.game_state()
+}
+
+// Puzzle mode: proves a mate in at most max_moves, and checks that the main search sees it too.
+fn find_mate(fen: &str, max_moves: u32) {
+    let mut chess = Chess::new();
+    chess.setup_fen_string(fen);
+    let result = solve_mate(&mut chess, max_moves, DEFAULT_HASH_MB);
+    println!("{:?}, Nodes: {}, Time: {:?}", result.proof, result.nodes, result.time);
+    if result.proof == Proof::PROVEN {
+        println!("Mate in {}: {}", result.mate_in().unwrap(), pv_string(&result.pv));
+        let search = get_next_move(&mut chess, &SearchLimits::depth(result.pv.len() as i32), &mut StderrObserver);
+        println!("Alpha beta: {}", score_string::<Chess>(search.score));
+    }
+}
 
-    chess.console_draw();
+// Prints the search tree of the position's last iteration down to max_ply, as Graphviz DOT or JSON, to see why a
+// move is preferred.
+fn print_search_tree(fen: &str, depth: i32, max_ply: usize, dot: bool, options: SearchOptions) {
+    let mut chess = Chess::new();
+    chess.setup_fen_string(fen);
+    let options = SearchOptions { trace_ply: Some(max_ply), ..options };
+    let result = Engine::new(DEFAULT_HASH_MB, options).search(&mut chess, &SearchLimits::depth(depth), &mut StderrObserver);
+    let trace = result.trace.unwrap();
+    println!("{}", if dot { trace.to_dot() } else { trace.to_json() });
 }
 
 
+// --seed N breaks ties between equal moves with a seeded generator, so the same game replays the same way.
+fn search_options(args: &[String]) -> SearchOptions {
+    let mut options = SearchOptions::default();
+    if let Some(seed) = args.iter().position(|a| a == "--seed").and_then(|i| args.get(i + 1)) {
+        options.tie_break = TieBreak::SEEDED(seed.parse().expect("--seed takes a number"));
+    }
+    options
+}
+
+// --tree FEN DEPTH PLY [--dot] prints the search tree of the position instead of playing.
+// --mate FEN N looks for a mate in at most N moves in the position.
+// --vs-mcts SECONDS plays alpha-beta as white against MCTS, with SECONDS per move each.
 fn main() {
-    play_game_my_front(PLAYER1, 100);
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|a| a == "--tree") {
+        let arg = |k: usize| args.get(i + k).expect("--tree takes FEN DEPTH PLY");
+        let depth = arg(2).parse().expect("DEPTH is a number");
+        let max_ply = arg(3).parse().expect("PLY is a number");
+        print_search_tree(arg(1), depth, max_ply, args.iter().any(|a| a == "--dot"), search_options(&args));
+        return;
+    }
+    if let Some(i) = args.iter().position(|a| a == "--mate") {
+        let arg = |k: usize| args.get(i + k).expect("--mate takes FEN N");
+        find_mate(arg(1), arg(2).parse().expect("N is a number"));
+        return;
+    }
+    if let Some(i) = args.iter().position(|a| a == "--vs-mcts") {
+        let seconds = args.get(i + 1).expect("--vs-mcts takes SECONDS").parse().expect("SECONDS is a number");
+        let result = match play_against_mcts(PLAYER2, Duration::from_secs_f64(seconds)) {
+            GameState::PLAYER1WIN => "Alpha-beta wins",
+            GameState::PLAYER2WIN => "MCTS wins",
+            GameState::TIE => "Draw",
+            GameState::PLAYING => "Unfinished",
+        };
+        println!("{}", result);
+        return;
+    }
+    play_game_my_front(PLAYER1, 100, search_options(&args));
 }
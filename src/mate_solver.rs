@@ -0,0 +1,182 @@
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+use ahash::AHashMap;
+use crate::chess_impl::{Chess, Move};
+use crate::two_player_game::Game;
+
+const INFINITY: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Proof {
+    PROVEN,
+    DISPROVEN,
+    // Ran out of memory first.
+    UNKNOWN,
+}
+
+pub struct MateResult {
+    pub proof: Proof,
+    // When proven, the mating line with the longest defence. Its length is the mate in plies, the first mate proven
+    // and not always the shortest, search again with fewer max_moves for that.
+    pub pv: Vec<Move>,
+    pub nodes: usize,
+    pub time: Duration,
+}
+
+impl MateResult {
+    pub fn mate_in(&self) -> Option<i32> {
+        (self.proof == Proof::PROVEN).then(|| (self.pv.len() as i32 + 1) / 2)
+    }
+}
+
+struct Node {
+    // The move into this node, None for the root.
+    m: Option<Move>,
+    hash: u64,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // Proof and disproof numbers, 0 when solved.
+    pn: u32,
+    dn: u32,
+    // Plies from the root, the attacker moves on even plies.
+    ply: u32,
+    // When proven, plies to the mate.
+    mate: u32,
+    // The solved transposition this node was taken from, the line goes on there.
+    link: Option<usize>,
+}
+
+struct Solver {
+    nodes: Vec<Node>,
+    // Solved nodes by position hash.
+    solved: AHashMap<u64, usize>,
+    max_plies: u32,
+}
+
+// Proof-number search for a mate by the side to move in at most max_moves moves, within about memory_mb of nodes.
+// Repetitions and the fifty move rule are ignored, so the result only depends on the position and transpositions are safe.
+pub fn solve_mate(chess: &mut Chess, max_moves: u32, memory_mb: usize) -> MateResult {
+    let start = Instant::now();
+    let max_nodes = (memory_mb << 20) / (size_of::<Node>() + size_of::<usize>() + size_of::<(u64, usize)>());
+    let mut solver = Solver { nodes: vec![], solved: AHashMap::new(), max_plies: (2 * max_moves).saturating_sub(1) };
+    let root = solver.new_node(chess, None, None, 0);
+    solver.nodes.push(root);
+
+    while solver.nodes[0].pn != 0 && solver.nodes[0].dn != 0 && solver.nodes.len() < max_nodes {
+        let mut played = 0;
+        let mut node = 0;
+        while !solver.nodes[node].children.is_empty() {
+            node = solver.most_proving_child(node);
+            chess.do_move(solver.nodes[node].m.clone().unwrap());
+            played += 1;
+        }
+
+        solver.expand(chess, node);
+        solver.update(node);
+        for _ in 0..played {
+            chess.undo_move();
+        }
+    }
+
+    let root = &solver.nodes[0];
+    let proof = if root.pn == 0 { Proof::PROVEN } else if root.dn == 0 { Proof::DISPROVEN } else { Proof::UNKNOWN };
+    MateResult {
+        proof,
+        pv: if proof == Proof::PROVEN { solver.mating_line() } else { vec![] },
+        nodes: solver.nodes.len(),
+        time: start.elapsed(),
+    }
+}
+
+impl Solver {
+    // The node for the current position of chess.
+    fn new_node(&self, chess: &mut Chess, m: Option<Move>, parent: Option<usize>, ply: u32) -> Node {
+        let hash = chess.get_hash();
+        let attacker = ply.is_multiple_of(2);
+        let moves = chess.possible_moves().len();
+        let mut node = Node { m, hash, parent, children: vec![], pn: 1, dn: 1, ply, mate: 0, link: None };
+
+        let transposition = self.solved.get(&hash).map(|&i| &self.nodes[i]).filter(|t| {
+            (t.pn == 0 && t.mate <= self.max_plies - ply) || (t.dn == 0 && t.ply <= ply)
+        });
+        if moves == 0 {
+            // Mated or stalemate, only the defender being mated is a proof.
+            (node.pn, node.dn) = if !attacker && chess.is_in_check() { (0, INFINITY) } else { (INFINITY, 0) };
+        } else if ply >= self.max_plies {
+            (node.pn, node.dn) = (INFINITY, 0);
+        } else if let Some(t) = transposition {
+            (node.pn, node.dn, node.mate) = (t.pn, t.dn, t.mate);
+            node.link = self.solved.get(&hash).copied();
+        } else if attacker {
+            node.dn = moves as u32;
+        } else {
+            node.pn = moves as u32;
+        }
+        node
+    }
+
+    // At the attacker's nodes the child that is easiest to prove, at the defender's the easiest to disprove.
+    fn most_proving_child(&self, node: usize) -> usize {
+        let children = self.nodes[node].children.iter().copied();
+        if self.nodes[node].ply.is_multiple_of(2) {
+            children.min_by_key(|&c| self.nodes[c].pn).unwrap()
+        } else {
+            children.min_by_key(|&c| self.nodes[c].dn).unwrap()
+        }
+    }
+
+    fn expand(&mut self, chess: &mut Chess, node: usize) {
+        let ply = self.nodes[node].ply + 1;
+        for m in chess.possible_moves() {
+            chess.do_move(m.clone());
+            let child = self.new_node(chess, Some(m), Some(node), ply);
+            chess.undo_move();
+            self.nodes.push(child);
+            let child = self.nodes.len() - 1;
+            self.nodes[node].children.push(child);
+        }
+    }
+
+    // Recomputes the numbers from node up to the root.
+    fn update(&mut self, node: usize) {
+        let mut next = Some(node);
+        while let Some(i) = next {
+            let children = &self.nodes[i].children;
+            let numbers = children.iter().map(|&c| (self.nodes[c].pn, self.nodes[c].dn));
+            let (pn, dn, mate) = if self.nodes[i].ply.is_multiple_of(2) {
+                let mate = children.iter().filter(|&&c| self.nodes[c].pn == 0).map(|&c| self.nodes[c].mate + 1).min();
+                (numbers.clone().map(|(pn, _)| pn).min().unwrap(), numbers.fold(0u32, |s, (_, dn)| s.saturating_add(dn)), mate)
+            } else {
+                let mate = children.iter().map(|&c| self.nodes[c].mate + 1).max();
+                (numbers.clone().fold(0u32, |s, (pn, _)| s.saturating_add(pn)), numbers.map(|(_, dn)| dn).min().unwrap(), mate)
+            };
+
+            let node = &mut self.nodes[i];
+            (node.pn, node.dn, node.mate) = (pn, dn, if pn == 0 { mate.unwrap() } else { 0 });
+            if pn == 0 || dn == 0 {
+                self.solved.entry(node.hash).or_insert(i);
+            }
+            next = node.parent;
+        }
+    }
+
+    // The attacker's fastest mate against the defender's longest resistance, following transpositions.
+    fn mating_line(&self) -> Vec<Move> {
+        let mut pv = vec![];
+        let mut node = 0;
+        loop {
+            while let Some(link) = self.nodes[node].link {
+                node = link;
+            }
+            let children = self.nodes[node].children.iter().copied();
+            let next = if self.nodes[node].ply.is_multiple_of(2) {
+                children.filter(|&c| self.nodes[c].pn == 0).min_by_key(|&c| self.nodes[c].mate)
+            } else {
+                children.max_by_key(|&c| self.nodes[c].mate)
+            };
+            let Some(next) = next else { return pv };
+            pv.push(self.nodes[next].m.clone().unwrap());
+            node = next;
+        }
+    }
+}
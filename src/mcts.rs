@@ -0,0 +1,289 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use crate::alpha_beta::{MoveResult, PvLine};
+use crate::two_player_game::{Game, GameState, Scored, SearchMove};
+use crate::two_player_game::Player::{self, PLAYER1};
+use crate::time_manager::TimeManager;
+use crate::search_limits::SearchLimits;
+
+// Playouts per search when the limits give neither nodes nor time.
+const DEFAULT_PLAYOUTS: u64 = 10_000;
+// Expected results are clamped to this before turning them into a score, so a sure win is 1200.
+const MAX_EXPECTED: f64 = 0.999;
+
+// How a playout from a new tree node is scored.
+pub enum Playout<G> {
+    // Random moves until the game ends.
+    RANDOM,
+    // Random moves for at most plies, then evaluate gives PLAYER1's expected result, from 0 to 1.
+    EVAL { plies: usize, evaluate: fn(&G) -> f64 },
+}
+
+pub struct MctsOptions<G> {
+    // The c in the UCT formula, wins / visits + c * sqrt(ln parent_visits / visits).
+    pub exploration: f64,
+    pub playout: Playout<G>,
+    // Random playouts that don't end in this many plies count as a draw.
+    pub max_playout_plies: usize,
+    // Same seed, same position and a playout limit give the same move.
+    pub seed: Option<u64>,
+}
+
+impl<G> Default for MctsOptions<G> {
+    fn default() -> Self {
+        MctsOptions {
+            exploration: std::f64::consts::SQRT_2,
+            playout: Playout::RANDOM,
+            max_playout_plies: 200,
+            seed: None,
+        }
+    }
+}
+
+// PLAYER1's expected result from Scored::get_score, for Playout::EVAL.
+pub fn score_evaluation<G: Scored<ScoreType = i32>>(game: &G) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-game.get_score() as f64 / 400.0))
+}
+
+// An expected result as a score, the inverse of score_evaluation.
+fn expected_to_score(expected: f64) -> i32 {
+    let expected = expected.clamp(1.0 - MAX_EXPECTED, MAX_EXPECTED);
+    (400.0 * (expected / (1.0 - expected)).log10()).round() as i32
+}
+
+struct Node<M> {
+    // The move into this node, None for a new root.
+    m: Option<M>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // Moves without a child yet.
+    untried: Vec<M>,
+    visits: u32,
+    // Sum of playout results for the player who made m.
+    wins: f64,
+    player: Player,
+}
+
+impl<M> Node<M> {
+    fn expected(&self) -> f64 {
+        self.wins / self.visits as f64
+    }
+}
+
+// Monte Carlo tree search with UCT, for any Game. The tree is kept between moves, tell it every move played with advance.
+pub struct Mcts<G: Game> {
+    pub options: MctsOptions<G>,
+    // The root is the first node, empty before the first search.
+    nodes: Vec<Node<G::MoveType>>,
+    rng: StdRng,
+    stop: Arc<AtomicBool>,
+}
+
+impl<G: Game<MoveType: SearchMove>> Mcts<G> {
+    pub fn new(options: MctsOptions<G>) -> Mcts<G> {
+        let rng = match options.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Mcts { options, nodes: vec![], rng, stop: Arc::new(AtomicBool::new(false)) }
+    }
+
+    // Checked every 64 playouts, the search then returns the most visited move so far.
+    #[allow(dead_code)]
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    // Keeps the subtree of m, a move played in the searched position.
+    pub fn advance(&mut self, m: &G::MoveType) {
+        let child = self.nodes.first().and_then(|root| {
+            root.children.iter().copied().find(|&c| self.nodes[c].m.as_ref().is_some_and(|cm| cm.hash() == m.hash()))
+        });
+        match child {
+            Some(child) => self.reroot(child),
+            None => self.nodes.clear(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn tree_size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // Limits nodes count playouts, depth and mate don't apply.
+    pub fn search(&mut self, game: &mut G, limits: &SearchLimits<G::MoveType>) -> MoveResult<G> {
+        let start = Instant::now();
+        self.stop.store(false, Ordering::Relaxed);
+        let deadline = if limits.infinite { None } else { TimeManager::new(limits.time_control).soft_limit().map(|d| start + d) };
+        let playouts = limits.nodes.unwrap_or(if deadline.is_some() || limits.infinite { u64::MAX } else { DEFAULT_PLAYOUTS });
+
+        let root_moves = root_moves(game, limits);
+        if !self.root_matches(&root_moves) {
+            self.nodes.clear();
+        }
+        if self.nodes.is_empty() {
+            let player = game.current_player().other();
+            self.nodes.push(Node { m: None, parent: None, children: vec![], untried: root_moves, visits: 0, wins: 0.0, player });
+        }
+
+        let mut count = 0;
+        let mut seldepth = 0;
+        while count < playouts {
+            if count % 64 == 0 && (self.stop.load(Ordering::Relaxed) || deadline.is_some_and(|d| Instant::now() >= d)) {
+                break;
+            }
+            seldepth = seldepth.max(self.iterate(game));
+            count += 1;
+        }
+
+        self.result(count, seldepth, start.elapsed())
+    }
+
+    // One selection, expansion, playout and backpropagation. Returns the depth of the new node.
+    fn iterate(&mut self, game: &mut G) -> usize {
+        let mut node = 0;
+        let mut played = 0;
+        while self.nodes[node].untried.is_empty() && !self.nodes[node].children.is_empty() {
+            node = self.select_child(node);
+            game.do_move(self.nodes[node].m.clone().unwrap());
+            played += 1;
+        }
+
+        if !self.nodes[node].untried.is_empty() {
+            let i = self.rng.gen_range(0..self.nodes[node].untried.len());
+            let m = self.nodes[node].untried.swap_remove(i);
+            let player = game.current_player();
+            game.do_move(m.clone());
+            played += 1;
+            let child = Node { m: Some(m), parent: Some(node), children: vec![], untried: game.possible_moves(), visits: 0, wins: 0.0, player };
+            self.nodes.push(child);
+            let child = self.nodes.len() - 1;
+            self.nodes[node].children.push(child);
+            node = child;
+        }
+
+        let result = self.playout(game);
+
+        let mut next = Some(node);
+        while let Some(i) = next {
+            let node = &mut self.nodes[i];
+            node.visits += 1;
+            node.wins += if node.player == PLAYER1 { result } else { 1.0 - result };
+            next = node.parent;
+        }
+
+        for _ in 0..played {
+            game.undo_move();
+        }
+        played
+    }
+
+    fn select_child(&self, node: usize) -> usize {
+        let log_visits = (self.nodes[node].visits as f64).ln();
+        let uct = |c: usize| {
+            let child = &self.nodes[c];
+            child.expected() + self.options.exploration * (log_visits / child.visits as f64).sqrt()
+        };
+        self.nodes[node].children.iter().copied().max_by(|&a, &b| uct(a).total_cmp(&uct(b))).unwrap()
+    }
+
+    // PLAYER1's result of a playout from the game, which is left as it was.
+    fn playout(&mut self, game: &mut G) -> f64 {
+        let (plies, evaluate) = match self.options.playout {
+            Playout::RANDOM => (self.options.max_playout_plies, None),
+            Playout::EVAL { plies, evaluate } => (plies, Some(evaluate)),
+        };
+
+        let mut played = 0;
+        let result = loop {
+            let mut moves = game.possible_moves();
+            if moves.is_empty() || played >= plies {
+                break match game.game_state() {
+                    GameState::PLAYER1WIN => 1.0,
+                    GameState::PLAYER2WIN => 0.0,
+                    GameState::TIE => 0.5,
+                    GameState::PLAYING => evaluate.map_or(0.5, |evaluate| evaluate(game)),
+                };
+            }
+            let i = self.rng.gen_range(0..moves.len());
+            game.do_move(moves.swap_remove(i));
+            played += 1;
+        };
+
+        for _ in 0..played {
+            game.undo_move();
+        }
+        result
+    }
+
+    // A reused tree has to be for the same moves, or the caller missed an advance.
+    fn root_matches(&self, root_moves: &[G::MoveType]) -> bool {
+        let Some(root) = self.nodes.first() else { return false };
+        let mut tree: Vec<u64> = root.children.iter().map(|&c| self.nodes[c].m.as_ref().unwrap().hash())
+            .chain(root.untried.iter().map(|m| m.hash())).collect();
+        let mut game: Vec<u64> = root_moves.iter().map(|m| m.hash()).collect();
+        tree.sort_unstable();
+        game.sort_unstable();
+        tree == game
+    }
+
+    // Makes the subtree of child the whole tree.
+    fn reroot(&mut self, child: usize) {
+        let mut old: Vec<_> = std::mem::take(&mut self.nodes).into_iter().map(Some).collect();
+        let mut queue = VecDeque::from([(child, None)]);
+        while let Some((i, parent)) = queue.pop_front() {
+            let mut node: Node<G::MoveType> = old[i].take().unwrap();
+            let index = self.nodes.len();
+            queue.extend(node.children.drain(..).map(|c| (c, Some(index))));
+            node.parent = parent;
+            if let Some(parent) = parent {
+                self.nodes[parent].children.push(index);
+            }
+            self.nodes.push(node);
+        }
+    }
+
+    // The most visited children from node on.
+    fn principal_variation(&self, mut node: usize) -> Vec<G::MoveType> {
+        let mut pv = vec![self.nodes[node].m.clone().unwrap()];
+        while let Some(&best) = self.nodes[node].children.iter().max_by_key(|&&c| self.nodes[c].visits) {
+            pv.push(self.nodes[best].m.clone().unwrap());
+            node = best;
+        }
+        pv
+    }
+
+    // Root moves by visits, the most visited is the move.
+    fn result(&self, playouts: u64, seldepth: usize, time: Duration) -> MoveResult<G> {
+        let mut children = self.nodes[0].children.clone();
+        children.sort_by_key(|&c| std::cmp::Reverse(self.nodes[c].visits));
+        let lines: Vec<PvLine<G>> = children.iter()
+            .map(|&c| PvLine { score: expected_to_score(self.nodes[c].expected()), pv: self.principal_variation(c) })
+            .collect();
+        let best = lines.first();
+
+        MoveResult {
+            chess_move: best.map(|line| line.pv[0].clone()),
+            // No iterations, the length of the pv instead.
+            move_from_depth: best.map_or(0, |line| line.pv.len() as i32),
+            pv: best.map_or(vec![], |line| line.pv.clone()),
+            score: best.map_or(0, |line| line.score),
+            seldepth,
+            nodes: playouts,
+            time,
+            lines,
+            trace: None,
+        }
+    }
+}
+
+fn root_moves<G: Game<MoveType: SearchMove>>(game: &G, limits: &SearchLimits<G::MoveType>) -> Vec<G::MoveType> {
+    let moves = game.possible_moves();
+    if limits.search_moves.is_empty() {
+        return moves;
+    }
+    moves.into_iter().filter(|m| limits.search_moves.iter().any(|s| s.hash() == m.hash())).collect()
+}
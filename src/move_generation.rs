@@ -1,77 +1,102 @@
-use lazy_static::lazy_static;
-use crate::bit_help::{Dir, ray, ray_until_blocker, index_to_place, iter_index, _ray, index, index_to_coord, coord_to_index};
+use crate::bit_help::{Dir, index, index_to_place, iter_index};
 use crate::two_player_game::Player::{PLAYER1, PLAYER2};
 use crate::two_player_game::Player;
 use crate::chess_impl::{BoardState, PieceType};
-use crate::chess_impl::PieceType::PAWN;
+use crate::chess_impl::PieceType::{PAWN, KNIGHT, BISHOP, ROOK, QUEEN, KING};
 use crate::print_u64;
+use crate::magic::Magic;
+
+// Columns by x coordinate. x = 0 is the h file and x = 7 is the a file.
+pub const FILE_H: u64 = 0x0101_0101_0101_0101;
+pub const FILE_A: u64 = 0x8080_8080_8080_8080;
+
+// Rows by y coordinate. y = 0 is white's back rank.
+pub const ROW_1: u64 = 0xFF;
+pub const ROW_3: u64 = 0xFF << 16;
+pub const ROW_6: u64 = 0xFF << 40;
+pub const ROW_8: u64 = 0xFF << 56;
+
+// The row a pawn lands on after a single push from its starting row, indexed by player.
+static DOUBLE_PUSH_ROWS: [u64; 2] = [ROW_3, ROW_6];
+pub static PROMOTION_ROWS: [u64; 2] = [ROW_8, ROW_1];
+
+// Shift a board forward (towards the enemy) from the point of view of player.
+#[inline]
+pub fn pawn_shift(player: Player, board: u64, amount: u32) -> u64 {
+    match player {
+        PLAYER1 => board << amount,
+        PLAYER2 => board >> amount
+    }
+}
+
+// Shift a board backwards from the point of view of player - the inverse of pawn_shift.
+#[inline]
+pub fn pawn_shift_back(player: Player, board: u64, amount: u32) -> u64 {
+    pawn_shift(player.other(), board, amount)
+}
+
+// Amounts to shift pawns by for a capture towards x + 1 and x - 1, indexed by player.
+pub static PAWN_EAST_SHIFT: [u32; 2] = [9, 7];
+pub static PAWN_WEST_SHIFT: [u32; 2] = [7, 9];
+
+// All single pushes for a set of pawns.
+#[inline]
+pub fn pawn_pushes(player: Player, pawns: u64, empty: u64) -> u64 {
+    pawn_shift(player, pawns, 8) & empty
+}
+
+// All double pushes for a set of pawns. Pawns not on their starting row are ignored.
+#[inline]
+pub fn pawn_double_pushes(player: Player, pawns: u64, empty: u64) -> u64 {
+    pawn_pushes(player, pawn_pushes(player, pawns, empty) & DOUBLE_PUSH_ROWS[player as usize], empty)
+}
+
+// Squares attacked towards x + 1 by a set of pawns.
+#[inline]
+pub fn pawn_attacks_east(player: Player, pawns: u64) -> u64 {
+    pawn_shift(player, pawns & !FILE_A, PAWN_EAST_SHIFT[player as usize])
+}
+
+// Squares attacked towards x - 1 by a set of pawns.
+#[inline]
+pub fn pawn_attacks_west(player: Player, pawns: u64) -> u64 {
+    pawn_shift(player, pawns & !FILE_H, PAWN_WEST_SHIFT[player as usize])
+}
+
+#[inline]
+pub fn pawn_attacks(player: Player, pawns: u64) -> u64 {
+    pawn_attacks_east(player, pawns) | pawn_attacks_west(player, pawns)
+}
 
-// These where generated such that for each square - multiplying the blockerboard with the magic for that square gives a unique set of the most significant X bits.
-const _ROOK_MAGICS: [u64; 64] = [
-    0xa8002c000108020_u64, 0x6c00049b0002001_u64, 0x100200010090040_u64, 0x2480041000800801_u64, 0x280028004000800_u64,
-    0x900410008040022_u64, 0x280020001001080_u64, 0x2880002041000080_u64, 0xa000800080400034_u64, 0x4808020004000_u64,
-    0x2290802004801000_u64, 0x411000d00100020_u64, 0x402800800040080_u64, 0xb000401004208_u64, 0x2409000100040200_u64,
-    0x1002100004082_u64, 0x22878001e24000_u64, 0x1090810021004010_u64, 0x801030040200012_u64, 0x500808008001000_u64,
-    0xa08018014000880_u64, 0x8000808004000200_u64, 0x201008080010200_u64, 0x801020000441091_u64, 0x800080204005_u64,
-    0x1040200040100048_u64, 0x120200402082_u64, 0xd14880480100080_u64, 0x12040280080080_u64, 0x100040080020080_u64,
-    0x9020010080800200_u64, 0x813241200148449_u64, 0x491604001800080_u64, 0x100401000402001_u64, 0x4820010021001040_u64,
-    0x400402202000812_u64, 0x209009005000802_u64, 0x810800601800400_u64, 0x4301083214000150_u64, 0x204026458e001401_u64,
-    0x40204000808000_u64, 0x8001008040010020_u64, 0x8410820820420010_u64, 0x1003001000090020_u64, 0x804040008008080_u64,
-    0x12000810020004_u64, 0x1000100200040208_u64, 0x430000a044020001_u64, 0x280009023410300_u64, 0xe0100040002240_u64,
-    0x200100401700_u64, 0x2244100408008080_u64, 0x8000400801980_u64, 0x2000810040200_u64, 0x8010100228810400_u64,
-    0x2000009044210200_u64, 0x4080008040102101_u64, 0x40002080411d01_u64, 0x2005524060000901_u64, 0x502001008400422_u64,
-    0x489a000810200402_u64, 0x1004400080a13_u64, 0x4000011008020084_u64, 0x26002114058042_u64
-];
-
-const _BISHOP_MAGICS: [u64; 64] = [
-    0x89a1121896040240_u64, 0x2004844802002010_u64, 0x2068080051921000_u64, 0x62880a0220200808_u64, 0x4042004000000_u64,
-    0x100822020200011_u64, 0xc00444222012000a_u64, 0x28808801216001_u64, 0x400492088408100_u64, 0x201c401040c0084_u64,
-    0x840800910a0010_u64, 0x82080240060_u64, 0x2000840504006000_u64, 0x30010c4108405004_u64, 0x1008005410080802_u64,
-    0x8144042209100900_u64, 0x208081020014400_u64, 0x4800201208ca00_u64, 0xf18140408012008_u64, 0x1004002802102001_u64,
-    0x841000820080811_u64, 0x40200200a42008_u64, 0x800054042000_u64, 0x88010400410c9000_u64, 0x520040470104290_u64,
-    0x1004040051500081_u64, 0x2002081833080021_u64, 0x400c00c010142_u64, 0x941408200c002000_u64, 0x658810000806011_u64,
-    0x188071040440a00_u64, 0x4800404002011c00_u64, 0x104442040404200_u64, 0x511080202091021_u64, 0x4022401120400_u64,
-    0x80c0040400080120_u64, 0x8040010040820802_u64, 0x480810700020090_u64, 0x102008e00040242_u64, 0x809005202050100_u64,
-    0x8002024220104080_u64, 0x431008804142000_u64, 0x19001802081400_u64, 0x200014208040080_u64, 0x3308082008200100_u64,
-    0x41010500040c020_u64, 0x4012020c04210308_u64, 0x208220a202004080_u64, 0x111040120082000_u64, 0x6803040141280a00_u64,
-    0x2101004202410000_u64, 0x8200000041108022_u64, 0x21082088000_u64, 0x2410204010040_u64, 0x40100400809000_u64,
-    0x822088220820214_u64, 0x40808090012004_u64, 0x910224040218c9_u64, 0x402814422015008_u64, 0x90014004842410_u64,
-    0x1000042304105_u64, 0x10008830412a00_u64, 0x2520081090008908_u64, 0x40102000a0a60140_u64,
-];
-
-// The number of bits needed to save all options for blockers in this square.
-const _ROOK_INDEX_BITS: [i32; 64] = [
-    12, 11, 11, 11, 11, 11, 11, 12,
-    11, 10, 10, 10, 10, 10, 10, 11,
-    11, 10, 10, 10, 10, 10, 10, 11,
-    11, 10, 10, 10, 10, 10, 10, 11,
-    11, 10, 10, 10, 10, 10, 10, 11,
-    11, 10, 10, 10, 10, 10, 10, 11,
-    11, 10, 10, 10, 10, 10, 10, 11,
-    12, 11, 11, 11, 11, 11, 11, 12
-];
-
-const _BISHOP_INDEX_BITS: [i32; 64] = [
-    6, 5, 5, 5, 5, 5, 5, 6,
-    5, 5, 5, 5, 5, 5, 5, 5,
-    5, 5, 7, 7, 7, 7, 5, 5,
-    5, 5, 7, 9, 9, 7, 5, 5,
-    5, 5, 7, 9, 9, 7, 5, 5,
-    5, 5, 7, 7, 7, 7, 5, 5,
-    5, 5, 5, 5, 5, 5, 5, 5,
-    6, 5, 5, 5, 5, 5, 5, 6
-];
 
+// Attack information of a position, from the point of view of the player to move.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AttackInfo {
+    // Squares attacked by each player, indexed by player.
+    pub attacks: [u64; 2],
+
+    // Squares attacked by the enemy, seeing through the king of the player to move.
+    pub king_danger: u64,
+
+    // Enemy pieces giving check.
+    pub checkers: u64,
+
+    // Enemy sliders pinning a piece to the king, and the pieces they pin.
+    pub pinners: u64,
+    pub pinned: u64,
+}
 
 pub struct MoveTables {
 
-    // All move location for each square - not including board edge
-    bishop_masks: [u64; 64],
-    rook_masks: [u64; 64],
+    bishop_magics: [Magic; 64],
+    rook_magics: [Magic; 64],
+
+    // Map from magic index to moveboard, shared by all squares of both bishops and rooks.
+    slider_table: &'static [u64],
 
-    // Map from key to moveboard for each square
-    bishop_table: Vec<[u64; 1024]>,
-    rook_table: Vec<[u64; 4096]>,
+    // Same as slider_table, but indexed by pext of the blockers with the magic mask.
+    #[cfg(feature = "pext")]
+    pext_slider_table: &'static [u64],
 
     // Non sliding piece masks
     knight_masks: [u64; 64],
@@ -80,40 +105,65 @@ pub struct MoveTables {
     rays: [[u64; 64]; 8],
 }
 
-lazy_static! {
-    pub static ref MOVE_TABLE: MoveTables = {
-        MoveTables::new()
-    };
-}
+// Generated by build.rs, defines MOVE_TABLE.
+include!(concat!(env!("OUT_DIR"), "/move_tables.rs"));
 
-impl MoveTables {
+#[cfg(feature = "pext")]
+pub mod pext {
+    use std::sync::atomic::{AtomicU8, Ordering};
+    use crate::magic::Magic;
+
+    const UNKNOWN: u8 = 0;
+    const OFF: u8 = 1;
+    const ON: u8 = 2;
 
-    pub fn new() -> MoveTables {
-        let mut m = MoveTables {
-            bishop_masks: [0; 64],
-            rook_masks: [0; 64],
-            bishop_table: vec![[0; 1024]; 64],
-            rook_table: vec![[0; 4096]; 64],
-            knight_masks: [0; 64],
-            king_masks: [0; 64],
-            rays: [[0; 64]; 8],
-        };
-        m._init();
-        m
+    static PEXT_STATE: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+    fn cpu_has_bmi2() -> bool {
+        #[cfg(target_arch = "x86_64")]
+        return is_x86_feature_detected!("bmi2");
+        #[cfg(not(target_arch = "x86_64"))]
+        return false;
     }
 
-    fn _init(&mut self) {
-        self._init_bishop_masks();
-        self._init_rook_masks();
-        self._init_bishop_tables();
-        self._init_rook_tables();
+    // Whether slider moves are looked up with pext. Detects cpu support on first call.
+    #[inline]
+    pub fn pext_enabled() -> bool {
+        match PEXT_STATE.load(Ordering::Relaxed) {
+            ON => true,
+            OFF => false,
+            _ => {
+                set_pext_enabled(true);
+                PEXT_STATE.load(Ordering::Relaxed) == ON
+            }
+        }
+    }
 
-        self._init_knight_masks();
-        self._init_king_masks();
+    // Choose the slider lookup, e.g. for benchmarking. Pext stays off if the cpu doesn't support it.
+    pub fn set_pext_enabled(enabled: bool) {
+        PEXT_STATE.store(if enabled && cpu_has_bmi2() { ON } else { OFF }, Ordering::Relaxed);
+    }
 
-        self._init_rays();
+    // Only call when pext_enabled() is true.
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    pub fn pext_index(magic: &Magic, blockers: u64) -> usize {
+        #[target_feature(enable = "bmi2")]
+        unsafe fn pext(blockers: u64, mask: u64) -> u64 {
+            std::arch::x86_64::_pext_u64(blockers, mask)
+        }
+        // Safe because pext is only enabled after bmi2 was detected.
+        magic.offset + unsafe { pext(blockers, magic.mask) } as usize
     }
 
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn pext_index(_magic: &Magic, _blockers: u64) -> usize {
+        unreachable!("pext is never enabled on this architecture")
+    }
+}
+
+impl MoveTables {
+
     pub fn get_moves(&self, index: usize, player: Player, piece_type: PieceType, blockers: u64) -> u64 {
         // Does not include castle or en passant capture
 
@@ -129,13 +179,19 @@ impl MoveTables {
     }
 
     pub fn get_bishop_moves(&self, index: usize, blockers: u64) -> u64 {
-        let key = ((blockers & self.bishop_masks[index]).wrapping_mul(_BISHOP_MAGICS[index])) >> (64 - _BISHOP_INDEX_BITS[index]);
-        self.bishop_table[index][key as usize]
+        #[cfg(feature = "pext")]
+        if pext::pext_enabled() {
+            return self.pext_slider_table[pext::pext_index(&self.bishop_magics[index], blockers)];
+        }
+        self.slider_table[self.bishop_magics[index].index(blockers)]
     }
 
     pub fn get_rook_moves(&self, index: usize, blockers: u64) -> u64 {
-        let key = ((blockers & self.rook_masks[index]).wrapping_mul(_ROOK_MAGICS[index])) >> (64 - _ROOK_INDEX_BITS[index]);
-        self.rook_table[index][key as usize]
+        #[cfg(feature = "pext")]
+        if pext::pext_enabled() {
+            return self.pext_slider_table[pext::pext_index(&self.rook_magics[index], blockers)];
+        }
+        self.slider_table[self.rook_magics[index].index(blockers)]
     }
 
     pub fn get_knight_moves(&self, index: usize) -> u64 {
@@ -147,53 +203,73 @@ impl MoveTables {
     }
 
     pub fn get_pawn_moves(&self, player: Player, index: usize, blockers: u64) -> u64 {
-        let mut res = 0_u64;
-        let (start_row, row_diff) = match player {
-            PLAYER1 => (1, 2),
-            PLAYER2 => (6, -2)
-        };
-        let dir = player.dir(Dir::North);
-
-        if let Some(move_one) = dir.mv(index_to_place(index)) {
-            res |= move_one & !blockers;
-            if res != 0 && index / 8 == start_row {
-                res |= index_to_place((index as i32 + row_diff * 8) as usize) & !blockers;
-            }
-        }
-        res
+        let place = index_to_place(index);
+        pawn_pushes(player, place, !blockers) | pawn_double_pushes(player, place, !blockers)
     }
 
     pub fn get_pawn_captures(&self, player: Player, index: usize, enemy_blockers: u64) -> u64 {
-        let mut res = 0_u64;
+        pawn_attacks(player, index_to_place(index)) & enemy_blockers
+    }
+
+    // All squares attacked by player's pieces, with occ blocking the sliders.
+    pub fn get_attacks(&self, board: &BoardState, player: Player, occ: u64) -> u64 {
+        let mut attacks = pawn_attacks(player, board.get(player, PAWN));
 
-        for dir in [Dir::NorthEast, Dir::NorthWest].iter() {
-            if let Some(diag_place) = player.dir(*dir).mv(index_to_place(index)) {
-                res |= diag_place
+        for piece_type in PieceType::all().skip(1) {
+            for index in iter_index(board.get(player, piece_type)) {
+                attacks |= self.get_moves(index, player, piece_type, occ);
             }
         }
 
-        res &= enemy_blockers;
-        res
+        attacks
     }
 
-    pub fn get_king_danger_squares(&self, board: &BoardState, player: Player) -> u64 {
-        let mut king_danger = 0_u64;
-        let other = player.other();
-
-        let occ_no_king = board.all_occupancy() & !board.get(player, PieceType::KING);
+    pub fn get_attack_info(&self, board: &BoardState, player: Player) -> AttackInfo {
+        let enemy = player.other();
+        let king = board.get(player, KING);
+        let king_index = index(king);
+        let my_occ = board.occupancy(player);
+        let enemy_occ = board.occupancy(enemy);
+        let occ = my_occ | enemy_occ;
+
+        let enemy_rooks = board.get(enemy, ROOK) | board.get(enemy, QUEEN);
+        let enemy_bishops = board.get(enemy, BISHOP) | board.get(enemy, QUEEN);
+
+        let slider_checkers = (self.get_rook_moves(king_index, occ) & enemy_rooks)
+            | (self.get_bishop_moves(king_index, occ) & enemy_bishops);
+        let checkers = slider_checkers
+            | (self.get_knight_moves(king_index) & board.get(enemy, KNIGHT))
+            | self.get_pawn_captures(player, king_index, board.get(enemy, PAWN));
+
+        let mut attacks = [0; 2];
+        attacks[player as usize] = self.get_attacks(board, player, occ);
+        attacks[enemy as usize] = self.get_attacks(board, enemy, occ);
+
+        // The king can't step away from a checking slider along the line it is checked on.
+        let mut king_danger = attacks[enemy as usize];
+        for i in iter_index(slider_checkers) {
+            if index_to_place(i) & enemy_rooks != 0 {
+                king_danger |= self.get_rook_moves(i, occ & !king);
+            }
+            if index_to_place(i) & enemy_bishops != 0 {
+                king_danger |= self.get_bishop_moves(i, occ & !king);
+            }
+        }
 
-        for piece_type in PieceType::all() {
-            for index in iter_index(board.get(other, piece_type)) {
-                if piece_type == PAWN {
-                    king_danger |= self.get_pawn_captures(other, index, !0);
-                }
-                else {
-                    king_danger |= self.get_moves(index, other, piece_type, occ_no_king);
-                }
+        // Pinners see the king through exactly one of the player's pieces.
+        let mut pinners = 0;
+        let mut pinned = 0;
+        let candidates = (self.get_rook_moves(king_index, enemy_occ) & enemy_rooks)
+            | (self.get_bishop_moves(king_index, enemy_occ) & enemy_bishops);
+        for i in iter_index(candidates) {
+            let pin = self.get_ray(king_index, i) & my_occ;
+            if pin.count_ones() == 1 {
+                pinners |= index_to_place(i);
+                pinned |= pin;
             }
         }
 
-        king_danger
+        AttackInfo { attacks, king_danger, checkers, pinners, pinned }
     }
 
     pub fn get_ray(&self, from: usize, to: usize) -> u64 {
@@ -204,111 +280,94 @@ impl MoveTables {
         }
         res
     }
+}
 
-    fn _create_blockers_from_index(index: i32, mut mask: u64) -> u64 {
-        let mut blockers: u64 = 0;
-        let bits = mask.count_ones();
-        for i in 0..bits {
-            let bit_pos = mask.trailing_zeros();
-            mask ^= mask & (!mask + 1);
-            if (index & (1 << i)) != 0 {
-                blockers |= 1_u64 << bit_pos
-            }
-        }
-        blockers
-    }
-
-    fn _init_bishop_masks(&mut self) {
-        for index in 0..64 {
-            self.bishop_masks[index] |= _ray(index, Dir::NorthEast, 1);
-            self.bishop_masks[index] |= _ray(index, Dir::NorthWest, 1);
-            self.bishop_masks[index] |= _ray(index, Dir::SouthEast, 1);
-            self.bishop_masks[index] |= _ray(index, Dir::SouthWest, 1);
-        }
-    }
-
-    fn _init_rook_masks(&mut self) {
-        for index in 0..64 {
-            self.rook_masks[index] |= _ray(index, Dir::North, 1);
-            self.rook_masks[index] |= _ray(index, Dir::East, 1);
-            self.rook_masks[index] |= _ray(index, Dir::West, 1);
-            self.rook_masks[index] |= _ray(index, Dir::South, 1);
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use crate::move_generation::MOVE_TABLE;
+    use crate::table_gen::{subsets, find_magic, rook_moves_slow, bishop_moves_slow, MagicRng, MAGIC_SEED, generate};
+    use crate::bit_help::index_to_place;
 
-    fn _init_bishop_tables(&mut self) {
+    #[test]
+    fn test_slider_tables() {
         for index in 0..64 {
-            for blocker_index in 0..1<<_BISHOP_INDEX_BITS[index] {
-                let blockers = MoveTables::_create_blockers_from_index(blocker_index as i32, self.bishop_masks[index]);
-                let key = (blockers.wrapping_mul(_BISHOP_MAGICS[index])) >> (64 - _BISHOP_INDEX_BITS[index]);
-                self.bishop_table[index][key as usize] = MoveTables::_bishop_moves_slow(index, blockers);
+            for blockers in subsets(MOVE_TABLE.rook_magics[index].mask) {
+                assert_eq!(MOVE_TABLE.get_rook_moves(index, blockers), rook_moves_slow(index, blockers));
+            }
+            for blockers in subsets(MOVE_TABLE.bishop_magics[index].mask) {
+                assert_eq!(MOVE_TABLE.get_bishop_moves(index, blockers), bishop_moves_slow(index, blockers));
             }
         }
     }
 
-    fn _init_rook_tables(&mut self) {
+    #[test]
+    fn test_slider_moves_ignore_outside_blockers() {
+        // Blockers on the edges and on the square itself are outside the masks and must not change the result.
+        let noise = 0xFF00_0000_0000_00FF_u64 | 0x8181_8181_8181_8181;
         for index in 0..64 {
-            for blocker_index in 0..1<<_ROOK_INDEX_BITS[index] {
-                let blockers = MoveTables::_create_blockers_from_index(blocker_index as i32, self.rook_masks[index]);
-                let key = (blockers.wrapping_mul(_ROOK_MAGICS[index])) >> (64 - _ROOK_INDEX_BITS[index]);
-                self.rook_table[index][key as usize] = MoveTables::_rook_moves_slow(index, blockers);
+            for blockers in subsets(MOVE_TABLE.rook_magics[index].mask) {
+                let blockers = blockers | (noise & !index_to_place(index));
+                assert_eq!(MOVE_TABLE.get_rook_moves(index, blockers), rook_moves_slow(index, blockers));
             }
-        }
-    }
-
-    fn _init_knight_masks(&mut self) {
-        for i in 0..64 {
-            let (x, y) = index_to_coord(i);
-            for dx in [-2_i32, -1, 1, 2].iter().cloned() {
-                for dy in [-2_i32, -1, 1, 2].iter().cloned() {
-                    if dx.abs() != dy.abs() {
-                        let (nx, ny) = (x + dx, y + dy);
-                        if 0 <= nx && nx < 8 && 0 <= ny && ny < 8 {
-                            self.knight_masks[i] |= index_to_place(coord_to_index((nx, ny)));
-                        }
-                    }
-                }
+            for blockers in subsets(MOVE_TABLE.bishop_magics[index].mask) {
+                let blockers = blockers | (noise & !index_to_place(index));
+                assert_eq!(MOVE_TABLE.get_bishop_moves(index, blockers), bishop_moves_slow(index, blockers));
             }
         }
     }
 
-    fn _init_king_masks(&mut self) {
-        for index in 0..64 {
-            for dir in Dir::all() {
-                if let Some(m) = dir.mv(index_to_place(index)) {
-                    self.king_masks[index] |= m;
-                }
-            }
-        }
+    #[test]
+    fn test_slider_table_size() {
+        // Fancy magics only need 2^bits entries per square.
+        assert_eq!(MOVE_TABLE.slider_table.len(), 102400 + 5248);
     }
 
-    fn _init_rays(&mut self) {
-        for dir in Dir::all() {
-            for index in 0..64 {
-                self.rays[dir as usize][index] = ray(index, dir);
-            }
-        }
+    #[test]
+    fn test_subsets() {
+        assert_eq!(subsets(0).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(subsets(0b1010).collect::<Vec<_>>(), vec![0, 0b10, 0b1000, 0b1010]);
     }
 
-    fn _bishop_moves_slow(index: usize, blockers: u64) -> u64 {
-        let mut res = 0_u64;
-
-        res |= ray_until_blocker(index, blockers, Dir::NorthWest);
-        res |= ray_until_blocker(index, blockers, Dir::NorthEast);
-        res |= ray_until_blocker(index, blockers, Dir::SouthWest);
-        res |= ray_until_blocker(index, blockers, Dir::SouthEast);
+    #[test]
+    fn test_find_magic_is_deterministic() {
+        let mask = MOVE_TABLE.rook_magics[0].mask;
+        let (m1, _) = find_magic(mask, |b| rook_moves_slow(0, b), &mut MagicRng::new(MAGIC_SEED));
+        let (m2, _) = find_magic(mask, |b| rook_moves_slow(0, b), &mut MagicRng::new(MAGIC_SEED));
+        assert_eq!(m1, m2);
+        assert_eq!(m1, MOVE_TABLE.rook_magics[0].magic);
+    }
 
-        res
+    #[test]
+    fn test_generate_matches_build() {
+        let tables = generate();
+        assert!(tables.verify());
+        assert_eq!(tables.slider_table.as_slice(), MOVE_TABLE.slider_table);
+        assert_eq!(tables.knight_masks, MOVE_TABLE.knight_masks);
+        assert_eq!(tables.king_masks, MOVE_TABLE.king_masks);
+        assert_eq!(tables.rays, MOVE_TABLE.rays);
+
+        let mut source = Vec::new();
+        tables.write_source(&mut source, true).unwrap();
+        assert!(String::from_utf8(source).unwrap().contains("pub static MOVE_TABLE: MoveTables"));
     }
 
-    fn _rook_moves_slow(index: usize, blockers: u64) -> u64 {
-        let mut res = 0_u64;
+    #[cfg(feature = "pext")]
+    #[test]
+    fn test_pext_matches_magic() {
+        use crate::move_generation::pext::set_pext_enabled;
+        use crate::table_gen::generate;
 
-        res |= ray_until_blocker(index, blockers, Dir::North);
-        res |= ray_until_blocker(index, blockers, Dir::South);
-        res |= ray_until_blocker(index, blockers, Dir::East);
-        res |= ray_until_blocker(index, blockers, Dir::West);
+        let pext_table = generate().pext_slider_table();
+        assert_eq!(pext_table.as_slice(), MOVE_TABLE.pext_slider_table);
 
-        res
+        set_pext_enabled(true);
+        for index in 0..64 {
+            for blockers in subsets(MOVE_TABLE.rook_magics[index].mask) {
+                assert_eq!(MOVE_TABLE.get_rook_moves(index, blockers), rook_moves_slow(index, blockers));
+            }
+            for blockers in subsets(MOVE_TABLE.bishop_magics[index].mask) {
+                assert_eq!(MOVE_TABLE.get_bishop_moves(index, blockers), bishop_moves_slow(index, blockers));
+            }
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,109 @@
+use crate::two_player_game::{SearchMove, Searchable, Player};
+
+// History scores are kept within +-MAX_HISTORY, by the gravity in update_history.
+const MAX_HISTORY: i32 = 16384;
+
+// Sort keys, highest first. Quiet moves without a key are ordered by history, which stays between these.
+const HASH_MOVE: i32 = i32::MAX;
+const GOOD_CAPTURE: i32 = 3 * MAX_HISTORY;
+const KILLER: i32 = 2 * MAX_HISTORY + 2;
+const COUNTERMOVE: i32 = 2 * MAX_HISTORY;
+const BAD_CAPTURE: i32 = -3 * MAX_HISTORY;
+
+// Moves are kept as SearchMove::hash, like in the transposition table.
+pub struct MoveOrdering {
+    // Two quiet moves per ply that caused a cutoff, the newest first.
+    killers: Vec<[Option<u64>; 2]>,
+    // Butterfly history - how well quiet moves did, by player, from and to index.
+    history: Box<[[[i32; 64]; 64]; 2]>,
+    // The quiet move that refuted a move, by that move's from and to index.
+    countermoves: Box<[[Option<u64>; 64]; 64]>,
+}
+
+impl MoveOrdering {
+    pub fn new() -> MoveOrdering {
+        MoveOrdering {
+            killers: vec![],
+            history: Box::new([[[0; 64]; 64]; 2]),
+            countermoves: Box::new([[None; 64]; 64]),
+        }
+    }
+
+    // Killers are by ply, they don't carry over to the next position. History is halved so it adapts to it.
+    pub fn new_search(&mut self) {
+        self.killers.clear();
+        for entry in self.history.iter_mut().flatten().flatten() {
+            *entry /= 2;
+        }
+    }
+
+    pub fn killers(&self, ply: usize) -> [Option<u64>; 2] {
+        self.killers.get(ply).copied().unwrap_or([None; 2])
+    }
+
+    pub fn history(&self, player: Player, m: &impl SearchMove) -> i32 {
+        let (from, to) = m.squares();
+        self.history[player as usize][from][to]
+    }
+
+    pub fn countermove(&self, previous: Option<&impl SearchMove>) -> Option<u64> {
+        let previous = previous.filter(|p| !p.is_null())?;
+        let (from, to) = previous.squares();
+        self.countermoves[from][to]
+    }
+
+    // Sort key for m in game at ply, higher is searched first.
+    pub fn score<G: Searchable>(&self, game: &G, ply: usize, m: &G::MoveType, tt_move: Option<u64>, countermove: Option<u64>) -> i32 {
+        let hash = m.hash();
+        if tt_move == Some(hash) {
+            return HASH_MOVE;
+        }
+        if !m.is_quiet() {
+            // Promotions without a capture are good captures too.
+            return if !m.is_capture() || game.see(m) >= 0 { GOOD_CAPTURE + m.mvv_lva() } else { BAD_CAPTURE + m.mvv_lva() };
+        }
+
+        let killers = self.killers(ply);
+        if killers[0] == Some(hash) {
+            KILLER
+        } else if killers[1] == Some(hash) {
+            KILLER - 1
+        } else if countermove == Some(hash) {
+            COUNTERMOVE
+        } else {
+            self.history(game.current_player(), m)
+        }
+    }
+
+    // The quiet move m caused a cutoff at ply, after the quiet moves in tried failed to.
+    pub fn update_quiet_cutoff<G: Searchable>(&mut self, game: &G, ply: usize, depth: i32, m: &G::MoveType, tried: &[G::MoveType]) {
+        let hash = m.hash();
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None; 2]);
+        }
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(hash) {
+            killers[1] = killers[0];
+            killers[0] = Some(hash);
+        }
+
+        if let Some(previous) = game.last_move().filter(|p| !p.is_null()) {
+            let (from, to) = previous.squares();
+            self.countermoves[from][to] = Some(hash);
+        }
+
+        let player = game.current_player();
+        let bonus = (depth * depth).min(MAX_HISTORY);
+        self.update_history(player, m, bonus);
+        for other in tried {
+            self.update_history(player, other, -bonus);
+        }
+    }
+
+    // Gravity - the closer a score is to the limit, the less it moves towards it.
+    fn update_history(&mut self, player: Player, m: &impl SearchMove, bonus: i32) {
+        let (from, to) = m.squares();
+        let entry = &mut self.history[player as usize][from][to];
+        *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+    }
+}
@@ -0,0 +1,38 @@
+use std::time::Duration;
+use crate::time_manager::TimeControl;
+
+// When a search ends, whichever limit comes first. Nothing set searches to MAX_DEPTH.
+#[derive(Clone, Debug)]
+pub struct SearchLimits<M> {
+    pub depth: Option<i32>,
+    // Nodes of the main thread. With one thread and a fixed tie break, the same limit gives the same move on any machine.
+    pub nodes: Option<u64>,
+    pub time_control: TimeControl,
+    // Ignore the time control, search until stopped.
+    pub infinite: bool,
+    // Stop once a mate in this many moves or fewer is found.
+    pub mate: Option<i32>,
+    // Only consider these root moves, all of them when empty.
+    pub search_moves: Vec<M>,
+}
+
+impl<M> Default for SearchLimits<M> {
+    fn default() -> Self {
+        SearchLimits { depth: None, nodes: None, time_control: TimeControl::default(), infinite: false, mate: None, search_moves: vec![] }
+    }
+}
+
+impl<M> SearchLimits<M> {
+    pub fn depth(depth: i32) -> SearchLimits<M> {
+        SearchLimits { depth: Some(depth), ..SearchLimits::default() }
+    }
+
+    #[allow(dead_code)]
+    pub fn nodes(nodes: u64) -> SearchLimits<M> {
+        SearchLimits { nodes: Some(nodes), ..SearchLimits::default() }
+    }
+
+    pub fn movetime(movetime: Duration) -> SearchLimits<M> {
+        SearchLimits { time_control: TimeControl::movetime(movetime), ..SearchLimits::default() }
+    }
+}
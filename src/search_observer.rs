@@ -0,0 +1,57 @@
+use std::time::Duration;
+use crate::alpha_beta::{pv_string, score_string, MoveResult, PvLine};
+use crate::chess_impl::Chess;
+use crate::two_player_game::Searchable;
+
+// A completed iteration of the main search thread.
+pub struct IterationInfo<'a, G: Searchable = Chess> {
+    pub depth: i32,
+    pub seldepth: usize,
+    // From the point of view of the player to move.
+    pub score: i32,
+    pub pv: &'a [G::MoveType],
+    // With options.multi_pv, the best root moves, best first. The first is pv.
+    pub lines: &'a [PvLine<G>],
+    // Nodes of the main thread, in all iterations so far.
+    pub nodes: u64,
+    pub nps: u64,
+    // Permille of the transposition table written in this search.
+    pub hashfull: usize,
+    pub time: Duration,
+}
+
+// Receives the progress of a search. Only the main search thread reports, all callbacks do nothing by default.
+pub trait SearchObserver<G: Searchable = Chess> {
+    fn iteration_finished(&mut self, _info: &IterationInfo<G>) {}
+
+    // The root move the main thread starts searching, number counts from 1 in search order.
+    fn current_move(&mut self, _depth: i32, _m: &G::MoveType, _number: usize) {}
+
+    fn search_finished(&mut self, _result: &MoveResult<G>) {}
+}
+
+// Reports nothing.
+#[allow(dead_code)]
+pub struct NoObserver;
+
+impl<G: Searchable> SearchObserver<G> for NoObserver {}
+
+// Prints iterations and the result to stderr, for the command line.
+pub struct StderrObserver;
+
+impl<G: Searchable> SearchObserver<G> for StderrObserver {
+    fn iteration_finished(&mut self, info: &IterationInfo<G>) {
+        let m = info.pv.first().map(|m| m.to_string()).unwrap_or_default();
+        eprintln!("Depth: {}, SelDepth: {}, Move: {}, Score: {}, Nodes: {}, NPS: {}, Hashfull: {}, Time: {:?}, PV: {}", info.depth, info.seldepth, m, score_string::<G>(info.score), info.nodes, info.nps, info.hashfull, info.time, pv_string(info.pv));
+        for (k, line) in info.lines.iter().enumerate().skip(1) {
+            eprintln!("  Line: {}, Score: {}, PV: {}", k + 1, score_string::<G>(line.score), pv_string(&line.pv));
+        }
+    }
+
+    fn search_finished(&mut self, result: &MoveResult<G>) {
+        eprintln!("Move from depth: {}, SelDepth: {}, Score: {}, Nodes: {}, Time: {:?}, PV: {}", result.move_from_depth, result.seldepth, score_string::<G>(result.score), result.nodes, result.time, pv_string(&result.pv));
+        for line in result.lines.iter().skip(1) {
+            eprintln!("  Alternative: {}, PV: {}", score_string::<G>(line.score), pv_string(&line.pv));
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt::{Display, Write};
+
+// Why an alpha_beta node returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cutoff {
+    // Every move was searched, the score is exact or an upper bound.
+    NONE,
+    // A move scored at least b.
+    BETA,
+    TRANSPOSITION,
+    MATEDISTANCE,
+    REVERSEFUTILITY,
+    NULLMOVE,
+    DRAW,
+    // Mated or stalemate.
+    NOMOVES,
+    // Out of depth, the score is from quiescence.
+    QUIESCENCE,
+    // The search was stopped, there is no score.
+    STOPPED,
+}
+
+pub struct TraceNode {
+    // The move into the node, "root" for the first.
+    pub m: String,
+    pub ply: usize,
+    pub depth: i32,
+    // The window it was searched with.
+    pub a: i32,
+    pub b: i32,
+    pub score: Option<i32>,
+    pub cutoff: Cutoff,
+    // Nodes searched from it, itself and quiescence included.
+    pub nodes: u64,
+    pub children: Vec<usize>,
+}
+
+// The alpha_beta tree down to max_ply. Recorded with SearchOptions::trace_ply, or by setting SearchContext::trace
+// for a single alpha_beta call from the root.
+pub struct SearchTrace {
+    pub max_ply: usize,
+    // The first is the root.
+    pub nodes: Vec<TraceNode>,
+    // Entered and not returned yet, the innermost last.
+    open: Vec<usize>,
+}
+
+impl SearchTrace {
+    pub fn new(max_ply: usize) -> SearchTrace {
+        SearchTrace { max_ply, nodes: vec![], open: vec![] }
+    }
+
+    // Returns whether the node is recorded, then exit has to be called when it returns.
+    pub fn enter(&mut self, ply: usize, m: Option<&impl Display>, depth: i32, a: i32, b: i32, call_count: u64) -> bool {
+        if ply > self.max_ply {
+            return false;
+        }
+        let i = self.nodes.len();
+        let m = m.map_or("root".to_string(), |m| m.to_string());
+        // Counts from call_count until exit.
+        self.nodes.push(TraceNode { m, ply, depth, a, b, score: None, cutoff: Cutoff::NONE, nodes: call_count, children: vec![] });
+        if let Some(&parent) = self.open.last() {
+            self.nodes[parent].children.push(i);
+        }
+        self.open.push(i);
+        true
+    }
+
+    pub fn exit(&mut self, score: Option<i32>, call_count: u64) {
+        let node = &mut self.nodes[self.open.pop().unwrap()];
+        node.score = score;
+        node.nodes = call_count - node.nodes;
+        if score.is_none() {
+            node.cutoff = Cutoff::STOPPED;
+        }
+    }
+
+    // Records the cutoff of the node at ply, if it is recorded.
+    pub fn cutoff(&mut self, ply: usize, cutoff: Cutoff) {
+        if let Some(&i) = self.open.last() {
+            if self.nodes[i].ply == ply {
+                self.nodes[i].cutoff = cutoff;
+            }
+        }
+    }
+
+    // Nested objects from the root, children in search order.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        if !self.nodes.is_empty() {
+            self.write_json(&mut out, 0);
+        }
+        out
+    }
+
+    fn write_json(&self, out: &mut String, i: usize) {
+        let node = &self.nodes[i];
+        let score = node.score.map_or("null".to_string(), |s| s.to_string());
+        write!(out, "{{\"move\":{},\"ply\":{},\"depth\":{},\"a\":{},\"b\":{},\"score\":{},\"cutoff\":\"{:?}\",\"nodes\":{},\"children\":[",
+               json_string(&node.m), node.ply, node.depth, node.a, node.b, score, node.cutoff, node.nodes).unwrap();
+        for (k, &child) in node.children.iter().enumerate() {
+            if k > 0 {
+                out.push(',');
+            }
+            self.write_json(out, child);
+        }
+        out.push_str("]}");
+    }
+
+    // For Graphviz, e.g. dot -Tsvg.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph search {\n    node [shape=box];\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let score = node.score.map_or("-".to_string(), |s| s.to_string());
+            writeln!(out, "    n{} [label=\"{}\\ndepth {} [{}, {}]\\nscore {}\\n{:?}, {} nodes\"];",
+                     i, dot_string(&node.m), node.depth, node.a, node.b, score, node.cutoff, node.nodes).unwrap();
+            for child in &node.children {
+                writeln!(out, "    n{} -> n{};", i, child).unwrap();
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+// A JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Text for inside a quoted DOT label.
+fn dot_string(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
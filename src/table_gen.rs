@@ -0,0 +1,263 @@
+// Generation of the move tables.
+// This is shared with build.rs, which writes the tables out as statics at build time.
+// So it may only depend on bit_help and magic.
+use std::io::{self, Write};
+use crate::bit_help::{Dir, ray, ray_until_blocker, index_to_place, _ray, index_to_coord, coord_to_index};
+use crate::magic::Magic;
+
+pub struct GeneratedTables {
+    pub bishop_magics: [Magic; 64],
+    pub rook_magics: [Magic; 64],
+    pub slider_table: Vec<u64>,
+    pub knight_masks: [u64; 64],
+    pub king_masks: [u64; 64],
+    pub rays: [[u64; 64]; 8],
+}
+
+pub fn generate() -> GeneratedTables {
+    let mut slider_table = Vec::new();
+    let mut rng = MagicRng::new(MAGIC_SEED);
+    let rook_magics = build_magics(&rook_masks(), rook_moves_slow, &mut slider_table, &mut rng);
+    let bishop_magics = build_magics(&bishop_masks(), bishop_moves_slow, &mut slider_table, &mut rng);
+
+    GeneratedTables {
+        bishop_magics,
+        rook_magics,
+        slider_table,
+        knight_masks: knight_masks(),
+        king_masks: king_masks(),
+        rays: rays(),
+    }
+}
+
+impl GeneratedTables {
+    // Check every entry of the slider tables against the slow move generation.
+    pub fn verify(&self) -> bool {
+        (0..64).all(|index| {
+            verify_magic(index, &self.rook_magics[index], &self.slider_table, rook_moves_slow)
+                && verify_magic(index, &self.bishop_magics[index], &self.slider_table, bishop_moves_slow)
+        })
+    }
+
+    // The slider table laid out for pext lookups.
+    // Every square takes the same space as with fancy magics, so the magic masks and offsets are reused -
+    // only the order of the attack boards inside each square's slice differs.
+    pub fn pext_slider_table(&self) -> Vec<u64> {
+        let mut table = vec![0; self.slider_table.len()];
+        for index in 0..64 {
+            for (magic, attacks) in [(&self.rook_magics[index], rook_moves_slow as fn(usize, u64) -> u64), (&self.bishop_magics[index], bishop_moves_slow)].iter() {
+                // Subsets come in increasing order, so the n-th subset is the one pext maps to n.
+                for (n, blockers) in subsets(magic.mask).enumerate() {
+                    table[magic.offset + n] = attacks(index, blockers);
+                }
+            }
+        }
+        table
+    }
+
+    // Write the tables as rust source, defining the MOVE_TABLE static.
+    pub fn write_source(&self, out: &mut impl Write, with_pext: bool) -> io::Result<()> {
+        writeln!(out, "static SLIDER_TABLE: [u64; {}] = {};", self.slider_table.len(), u64_array(&self.slider_table))?;
+        if with_pext {
+            writeln!(out, "static PEXT_SLIDER_TABLE: [u64; {}] = {};", self.slider_table.len(), u64_array(&self.pext_slider_table()))?;
+        }
+        writeln!(out, "pub static MOVE_TABLE: MoveTables = MoveTables {{")?;
+        writeln!(out, "    bishop_magics: {},", magic_array(&self.bishop_magics))?;
+        writeln!(out, "    rook_magics: {},", magic_array(&self.rook_magics))?;
+        writeln!(out, "    slider_table: &SLIDER_TABLE,")?;
+        if with_pext {
+            writeln!(out, "    pext_slider_table: &PEXT_SLIDER_TABLE,")?;
+        }
+        writeln!(out, "    knight_masks: {},", u64_array(&self.knight_masks))?;
+        writeln!(out, "    king_masks: {},", u64_array(&self.king_masks))?;
+        let rays: Vec<String> = self.rays.iter().map(|r| u64_array(r)).collect();
+        writeln!(out, "    rays: [{}],", rays.join(",\n"))?;
+        writeln!(out, "}};")
+    }
+}
+
+fn u64_array(values: &[u64]) -> String {
+    let values: Vec<String> = values.iter().map(|v| format!("{:#x}", v)).collect();
+    format!("[{}]", values.join(","))
+}
+
+fn magic_array(magics: &[Magic; 64]) -> String {
+    let magics: Vec<String> = magics.iter()
+        .map(|m| format!("Magic {{ mask: {:#x}, magic: {:#x}, shift: {}, offset: {} }}", m.mask, m.magic, m.shift, m.offset))
+        .collect();
+    format!("[{}]", magics.join(",\n"))
+}
+
+fn bishop_masks() -> [u64; 64] {
+    let mut masks = [0; 64];
+    for (index, mask) in masks.iter_mut().enumerate() {
+        *mask = Dir::diag().fold(0, |m, dir| m | _ray(index, dir, 1));
+    }
+    masks
+}
+
+fn rook_masks() -> [u64; 64] {
+    let mut masks = [0; 64];
+    for (index, mask) in masks.iter_mut().enumerate() {
+        *mask = Dir::adj().fold(0, |m, dir| m | _ray(index, dir, 1));
+    }
+    masks
+}
+
+fn knight_masks() -> [u64; 64] {
+    let mut masks = [0; 64];
+    for (i, mask) in masks.iter_mut().enumerate() {
+        let (x, y) = index_to_coord(i);
+        for dx in [-2_i32, -1, 1, 2].iter().cloned() {
+            for dy in [-2_i32, -1, 1, 2].iter().cloned() {
+                if dx.abs() != dy.abs() {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if (0..8).contains(&nx) && (0..8).contains(&ny) {
+                        *mask |= index_to_place(coord_to_index((nx, ny)));
+                    }
+                }
+            }
+        }
+    }
+    masks
+}
+
+fn king_masks() -> [u64; 64] {
+    let mut masks = [0; 64];
+    for (index, mask) in masks.iter_mut().enumerate() {
+        for dir in Dir::all() {
+            if let Some(m) = dir.mv(index_to_place(index)) {
+                *mask |= m;
+            }
+        }
+    }
+    masks
+}
+
+fn rays() -> [[u64; 64]; 8] {
+    let mut rays = [[0; 64]; 8];
+    for dir in Dir::all() {
+        for (index, r) in rays[dir as usize].iter_mut().enumerate() {
+            *r = ray(index, dir);
+        }
+    }
+    rays
+}
+
+pub fn bishop_moves_slow(index: usize, blockers: u64) -> u64 {
+    Dir::diag().fold(0, |res, dir| res | ray_until_blocker(index, blockers, dir))
+}
+
+pub fn rook_moves_slow(index: usize, blockers: u64) -> u64 {
+    Dir::adj().fold(0, |res, dir| res | ray_until_blocker(index, blockers, dir))
+}
+
+// xorshift64* - deterministic, so the same magics are found on every build.
+pub struct MagicRng {
+    state: u64
+}
+
+impl MagicRng {
+    pub fn new(seed: u64) -> MagicRng {
+        MagicRng { state: seed.max(1) }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // Magics with few lit bits are found a lot faster.
+    pub fn sparse_u64(&mut self) -> u64 {
+        self.next_u64() & self.next_u64() & self.next_u64()
+    }
+}
+
+pub const MAGIC_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+pub struct SubsetIterator {
+    mask: u64,
+    cur: u64,
+    done: bool,
+}
+
+impl Iterator for SubsetIterator {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.cur;
+        // Carry-Rippler trick - counts up through the bits of the mask.
+        self.cur = self.cur.wrapping_sub(self.mask) & self.mask;
+        self.done = self.cur == 0;
+        Some(res)
+    }
+}
+
+// All subsets of mask, including 0 and mask itself.
+pub fn subsets(mask: u64) -> SubsetIterator {
+    SubsetIterator { mask, cur: 0, done: false }
+}
+
+// Find a magic for a single square. Returns the magic and the square's attack table, indexed by magic key.
+pub fn find_magic(mask: u64, attacks: impl Fn(u64) -> u64, rng: &mut MagicRng) -> (u64, Vec<u64>) {
+    let bits = mask.count_ones();
+    let shift = 64 - bits;
+    let blockers: Vec<u64> = subsets(mask).collect();
+    let reference: Vec<u64> = blockers.iter().map(|b| attacks(*b)).collect();
+
+    let mut table = vec![0_u64; 1 << bits];
+    // The attempt a table entry was written in, saves clearing the table between attempts.
+    let mut written_at = vec![0_u32; 1 << bits];
+
+    let mut attempt = 0_u32;
+    loop {
+        let magic = rng.sparse_u64();
+        // Magics that don't spread the mask into the top byte rarely work - skip them cheaply.
+        if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+            continue;
+        }
+
+        attempt += 1;
+        let mut good = true;
+        for (b, a) in blockers.iter().zip(reference.iter()) {
+            let key = (b.wrapping_mul(magic) >> shift) as usize;
+            if written_at[key] != attempt {
+                written_at[key] = attempt;
+                table[key] = *a;
+            } else if table[key] != *a {
+                good = false;
+                break;
+            }
+        }
+
+        if good {
+            return (magic, table);
+        }
+    }
+}
+
+// Find magics for all squares, appending their attack boards to the shared table.
+pub fn build_magics(masks: &[u64; 64], attacks: fn(usize, u64) -> u64, table: &mut Vec<u64>, rng: &mut MagicRng) -> [Magic; 64] {
+    let mut magics = [Magic { mask: 0, magic: 0, shift: 0, offset: 0 }; 64];
+    for (index, magic) in magics.iter_mut().enumerate() {
+        let (number, square_table) = find_magic(masks[index], |b| attacks(index, b), rng);
+        *magic = Magic {
+            mask: masks[index],
+            magic: number,
+            shift: 64 - masks[index].count_ones(),
+            offset: table.len(),
+        };
+        table.extend(square_table);
+    }
+    magics
+}
+
+// Check every blocker subset of the square against the slow move generation.
+pub fn verify_magic(index: usize, magic: &Magic, table: &[u64], attacks: fn(usize, u64) -> u64) -> bool {
+    subsets(magic.mask).all(|b| table[magic.index(b)] == attacks(index, b))
+}
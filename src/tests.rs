@@ -3,7 +3,23 @@
 #[cfg(test)]
 mod tests {
     use crate::chess_impl::{Chess, Move, PieceType};
-    use crate::two_player_game::Game;
+    use crate::two_player_game::{Game, GameState, Player, SearchMove, Searchable};
+    use crate::transposition::{TranspositionTable, Bound};
+    use crate::alpha_beta::{alpha_beta, get_next_move, quiescence, mate_in, score_string, pv_string, MoveResult, SearchContext, SearchOptions, TieBreak};
+    use crate::search_observer::{IterationInfo, NoObserver, SearchObserver};
+    use crate::engine::Engine;
+    use crate::search_options;
+    use crate::search_trace::{Cutoff, SearchTrace};
+    use crate::mate_solver::{solve_mate, Proof};
+    use crate::mcts::{score_evaluation, Mcts, MctsOptions, Playout};
+    use crate::two_player_game::Scored;
+    use crate::two_player_game::Player::{PLAYER1, PLAYER2};
+    use crate::move_ordering::MoveOrdering;
+    use crate::time_manager::{TimeControl, TimeManager};
+    use crate::search_limits::SearchLimits;
+    use std::time::{Duration, Instant};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
 
     fn count_positions(chess: &mut Chess, depth: i32) -> usize {
         if depth == 0 {
@@ -23,6 +39,11 @@ mod tests {
         res
     }
 
+    // The legal move written as move_str, e.g. "e2e4".
+    fn find_move(chess: &Chess, move_str: &str) -> Move {
+        chess.possible_moves().into_iter().find(|m| m.to_string() == move_str).unwrap()
+    }
+
     #[test]
     fn test_position() {
         let mut chess = Chess::new();
@@ -104,6 +125,709 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_attack_info() {
+        let mut chess = Chess::new();
+        assert!(!chess.is_in_check());
+
+        // Black check by bishop
+        chess.setup_fen_string("r6r/1b2k1bq/8/8/7B/8/8/R3K2R b QK - 3 2");
+        assert!(chess.is_in_check());
+        let info = chess.attack_info();
+        assert_eq!(info.checkers.count_ones(), 1);
+
+        // Undo gives back the cached info of the position before the move.
+        let m = chess.possible_moves()[0].clone();
+        chess.do_move(m);
+        chess.undo_move();
+        assert_eq!(chess.attack_info().checkers, info.checkers);
+
+        // White bishop on e2 pinned by the rook on e7.
+        chess.setup_fen_string("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1");
+        let info = chess.attack_info();
+        assert_eq!(info.pinned, 1 << 11);
+        assert_eq!(info.pinners, 1 << 51);
+        assert_ne!(info.attacks[1] & (1 << 11), 0);
+        assert_eq!(info.attacks[0] & (1 << 51), 0);
+    }
+
+    #[test]
+    fn test_zobrist_hash() {
+        let mut chess = Chess::new();
+        let start_hash = chess.get_hash();
+        assert_eq!(start_hash, chess.compute_hash());
+
+        // Knights out and back transposes to the start position.
+        for move_str in ["g1f3", "g8f6", "f3g1", "f6g8"].iter() {
+            chess.do_move(find_move(&chess, move_str));
+            assert_eq!(chess.get_hash(), chess.compute_hash());
+        }
+        assert_eq!(chess.get_hash(), start_hash);
+
+        // Castles, en passant and promotions keep the incremental hash right.
+        chess.setup_fen_string("r3k2r/1P4bq/8/3pP3/8/8/7B/R3K2R w KQkq d6 0 1");
+        for m in chess.possible_moves() {
+            let before = chess.get_hash();
+            chess.do_move(m);
+            assert_eq!(chess.get_hash(), chess.compute_hash());
+            chess.undo_move();
+            assert_eq!(chess.get_hash(), before);
+        }
+    }
+
+    #[test]
+    fn test_transposition_table() {
+        let mut tt = TranspositionTable::new(1);
+        assert!(tt.len().is_power_of_two());
+        assert!(tt.probe(42).is_none());
+
+        tt.new_search();
+        tt.store(42, 3, 17, Bound::LOWER, Some(7));
+        let entry = tt.probe(42).unwrap();
+        assert_eq!((entry.depth, entry.score, entry.bound, entry.best_move), (3, 17, Bound::LOWER, Some(7)));
+
+        // A shallower entry for another position doesn't replace a deeper one from the same search.
+        let other = 42 + tt.len() as u64;
+        tt.store(other, 1, 0, Bound::EXACT, None);
+        assert!(tt.probe(other).is_none());
+        assert!(tt.probe(42).is_some());
+
+        // The best move is kept when the same position is stored without one.
+        tt.store(42, 4, 20, Bound::UPPER, None);
+        assert_eq!(tt.probe(42).unwrap().best_move, Some(7));
+
+        // Entries from older searches are always replaced.
+        tt.new_search();
+        tt.store(other, 1, 0, Bound::EXACT, None);
+        assert!(tt.probe(other).is_some());
+        assert!(tt.hashfull() <= 1);
+    }
+
+    #[test]
+    fn test_principal_variation() {
+        let mut chess = Chess::new();
+        chess.setup_fen_string("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10");
+        let result = get_next_move(&mut chess, &SearchLimits::depth(5), &mut NoObserver);
+
+        assert_eq!(result.pv.first(), result.chess_move.as_ref());
+        assert_eq!(result.move_from_depth, 5);
+        assert!(result.seldepth >= 5);
+        assert!(result.nodes > 0);
+
+        // Every move of the line is legal in turn.
+        for m in result.pv.iter() {
+            assert!(chess.possible_moves().contains(m));
+            chess.do_move(m.clone());
+        }
+    }
+
+    #[test]
+    fn test_score_is_for_player_to_move() {
+        // Black is a queen up, whoever moves.
+        let mut chess = Chess::new();
+        chess.setup_fen_string("3qk3/8/8/8/8/8/8/4K3 b - - 0 1");
+        assert!(get_next_move(&mut chess, &SearchLimits::depth(4), &mut NoObserver).score > 0);
+
+        chess.setup_fen_string("3qk3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert!(get_next_move(&mut chess, &SearchLimits::depth(4), &mut NoObserver).score < 0);
+    }
+
+    #[test]
+    fn test_null_move() {
+        let mut chess = Chess::new();
+        chess.setup_fen_string("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1");
+        let hash = chess.get_hash();
+        chess.do_null_move();
+        assert!(chess.last_move().unwrap().is_null());
+        assert_eq!(chess.get_hash(), chess.compute_hash());
+        // The en passant square is gone, black is to move.
+        assert!(chess.possible_moves().iter().all(|m| m.to_string() != "e5d6"));
+        chess.undo_null_move();
+        assert_eq!(chess.get_hash(), hash);
+
+        // King and pawns only.
+        assert!(chess.has_non_pawn_material());
+        chess.setup_fen_string("4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 1");
+        assert!(!chess.has_non_pawn_material());
+    }
+
+    #[test]
+    fn test_search_options() {
+        // White wins the queen with a knight fork, with or without pruning.
+        let none = SearchOptions {
+            null_move_pruning: false,
+            late_move_reductions: false,
+            reverse_futility_pruning: false,
+            futility_pruning: false,
+            quiescence_check_evasions: false,
+            delta_pruning: false,
+            contempt: 0,
+            threads: 1,
+            multi_pv: 1,
+            tie_break: TieBreak::RANDOM,
+            trace_ply: None,
+        };
+        for options in [SearchOptions::default(), none].iter().copied() {
+            let mut chess = Chess::new();
+            chess.setup_fen_string("q3k3/8/8/3N4/8/8/8/4K3 w - - 0 1");
+            let result = Engine::new(1, options).search(&mut chess, &SearchLimits::depth(5), &mut NoObserver);
+            assert_eq!(result.chess_move.unwrap().to_string(), "d5c7");
+        }
+    }
+
+    #[test]
+    fn test_static_exchange() {
+        let mut chess = Chess::new();
+        let see = |chess: &Chess, move_str: &str| chess.see(&find_move(chess, move_str));
+
+        // Pawn for pawn.
+        chess.setup_fen_string("4k3/8/3p4/4p3/3P4/8/8/4K3 w - - 0 1");
+        assert_eq!(see(&chess, "d4e5"), 0);
+
+        // Rook for a defended pawn.
+        chess.setup_fen_string("4k3/8/3p4/4p3/8/8/8/4R1K1 w - - 0 1");
+        assert_eq!(see(&chess, "e1e5"), -400);
+
+        // The queen behind the rook wins the pawn, the defender only recaptures once.
+        chess.setup_fen_string("4k3/4r3/8/4p3/8/8/4R3/4Q1K1 w - - 0 1");
+        assert_eq!(see(&chess, "e2e5"), 100);
+    }
+
+    #[test]
+    fn test_quiescence() {
+        let tt = TranspositionTable::new(1);
+        let mut ctx = SearchContext::new(&tt);
+
+        // Black takes the hanging queen, the stand pat score alone would have white ahead.
+        let mut chess = Chess::new();
+        chess.setup_fen_string("4k3/8/8/3q4/3Q4/8/8/4K3 b - - 0 1");
+        assert!(quiescence(&mut chess, Chess::MIN_INFINITY, Chess::MAX_INFINITY, &mut ctx).unwrap() > 500);
+        assert!(ctx.quiescence_count > 1);
+        assert!(ctx.seldepth >= 1);
+
+        // Nothing to capture - the evaluation stands.
+        chess.setup_fen_string("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(quiescence(&mut chess, Chess::MIN_INFINITY, Chess::MAX_INFINITY, &mut ctx).unwrap(), chess.get_score());
+    }
+
+    #[test]
+    fn test_move_ordering() {
+        let mut chess = Chess::new();
+        chess.setup_fen_string("4k3/8/3p4/4p3/3P4/8/8/R3K3 w - - 0 1");
+        let (capture, a2, a3, a4) = (find_move(&chess, "d4e5"), find_move(&chess, "a1a2"), find_move(&chess, "a1a3"), find_move(&chess, "a1a4"));
+
+        let mut ordering = MoveOrdering::new();
+        ordering.update_quiet_cutoff(&chess, 2, 4, &a2, std::slice::from_ref(&a4));
+        assert_eq!(ordering.killers(2), [Some(a2.hash()), None]);
+        assert!(ordering.history(PLAYER1, &a2) > 0);
+        assert!(ordering.history(PLAYER1, &a4) < 0);
+
+        // Hash move > good captures > killers > countermove > history.
+        let score = |m: &Move, tt_move: Option<u64>, countermove: Option<u64>| ordering.score(&chess, 2, m, tt_move, countermove);
+        assert!(score(&a3, Some(a3.hash()), None) > score(&capture, None, None));
+        assert!(score(&capture, None, None) > score(&a2, None, None));
+        assert!(score(&a2, None, None) > score(&a3, None, Some(a3.hash())));
+        assert!(score(&a3, None, Some(a3.hash())) > score(&a4, None, None));
+
+        // History stays bounded however often a move cuts off.
+        for _ in 0..1000 {
+            ordering.update_quiet_cutoff(&chess, 2, 20, &a2, &[]);
+        }
+        assert!(ordering.history(PLAYER1, &a2) <= 16384);
+    }
+
+    #[test]
+    fn test_mate_score() {
+        // Back rank mate, found as the shortest mate even when searching deeper.
+        let mut chess = Chess::new();
+        chess.setup_fen_string("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1");
+        let result = get_next_move(&mut chess, &SearchLimits::depth(5), &mut NoObserver);
+        assert_eq!(result.chess_move.as_ref().unwrap().to_string(), "d1d8");
+        assert_eq!(mate_in::<Chess>(result.score), Some(1));
+        assert_eq!(score_string::<Chess>(result.score), "mate in 1");
+
+        // Two moves from being mated.
+        assert_eq!(mate_in::<Chess>(Chess::MIN_SCORE + 4), Some(-2));
+        assert_eq!(mate_in::<Chess>(350), None);
+    }
+
+    #[test]
+    fn test_draws() {
+        let mut chess = Chess::new();
+        let play = |chess: &mut Chess, move_str: &str| chess.do_move(find_move(chess, move_str));
+
+        // Knights out and back, twice.
+        for repetitions in 1..3 {
+            for move_str in ["g1f3", "g8f6", "f3g1", "f6g8"].iter() {
+                play(&mut chess, move_str);
+            }
+            assert_eq!(chess.repetitions(), repetitions);
+            assert!(chess.is_draw());
+        }
+        assert!(chess.game_state() == GameState::TIE);
+
+        // A pawn move makes the earlier positions unreachable.
+        play(&mut chess, "e2e4");
+        assert_eq!(chess.repetitions(), 0);
+
+        chess.setup_fen_string("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80");
+        assert!(chess.get_fen_string().contains(" 99 "));
+        play(&mut chess, "a1a2");
+        assert!(chess.is_fifty_move_draw());
+        chess.undo_move();
+        play(&mut chess, "e2e4");
+        assert!(!chess.is_fifty_move_draw());
+
+        chess.setup_fen_string("4k3/8/8/8/8/8/8/4KN2 w - - 0 1");
+        assert!(chess.has_insufficient_material());
+        // Bishops on the same color can't mate, on different colors they can.
+        chess.setup_fen_string("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1");
+        assert!(chess.has_insufficient_material());
+        chess.setup_fen_string("4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1");
+        assert!(!chess.has_insufficient_material());
+    }
+
+    #[test]
+    fn test_stalemate() {
+        // Stalemated, not mated.
+        let mut chess = Chess::new();
+        chess.setup_fen_string("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert!(chess.game_state() == GameState::TIE);
+        let result = get_next_move(&mut chess, &SearchLimits::depth(4), &mut NoObserver);
+        assert!(result.chess_move.is_none());
+        assert_eq!(result.score, 0);
+
+        // Qf7 stalemates, Qf8 mates.
+        chess.setup_fen_string("7k/8/6K1/8/8/8/8/5Q2 w - - 0 1");
+        let result = get_next_move(&mut chess, &SearchLimits::depth(4), &mut NoObserver);
+        assert_eq!(result.chess_move.unwrap().to_string(), "f1f8");
+        assert_eq!(mate_in::<Chess>(result.score), Some(1));
+
+        chess.setup_fen_string("5Q1k/8/6K1/8/8/8/8/8 b - - 0 1");
+        assert!(chess.game_state() == GameState::PLAYER1WIN);
+    }
+
+    #[test]
+    fn test_time_manager() {
+        let time = TimeManager::new(TimeControl::default());
+        assert!(time.soft_limit().is_none() && time.hard_limit().is_none());
+        assert!(!time.should_stop());
+
+        let time = TimeManager::new(TimeControl::movetime(Duration::from_secs(2)));
+        assert_eq!(time.soft_limit(), time.hard_limit());
+
+        let clock = TimeControl { remaining: Some(Duration::from_secs(60)), increment: Duration::from_secs(1), ..TimeControl::default() };
+        let mut time = TimeManager::new(clock);
+        let soft = time.soft_limit().unwrap();
+        assert!(soft < time.hard_limit().unwrap());
+        assert!(time.hard_limit().unwrap() < Duration::from_secs(60));
+
+        // A stable best move keeps the soft limit, a changing one or a dropping score stretches it.
+        time.iteration_finished(1, 50);
+        time.iteration_finished(1, 50);
+        assert_eq!(time.soft_limit().unwrap(), soft);
+        time.iteration_finished(2, 50);
+        let unstable = time.soft_limit().unwrap();
+        assert!(unstable > soft);
+        time.iteration_finished(2, -50);
+        assert!(time.soft_limit().unwrap() > unstable);
+        assert!(time.soft_limit().unwrap() <= time.hard_limit().unwrap());
+
+        // The search returns a move when its time is up, long before it runs out of depth. Slack for a loaded machine.
+        let mut chess = Chess::new();
+        let start = Instant::now();
+        let result = get_next_move(&mut chess, &SearchLimits::movetime(Duration::from_millis(300)), &mut NoObserver);
+        assert!(result.chess_move.is_some());
+        assert!(result.move_from_depth < 100);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_stop_flag() {
+        // Tells when an iteration is done, however slow the machine.
+        struct Iterated(Arc<AtomicBool>);
+
+        impl SearchObserver for Iterated {
+            fn iteration_finished(&mut self, _info: &IterationInfo) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        // An unlimited search, stopped from another thread, still returns the last completed iteration.
+        let mut engine = Engine::new(1, SearchOptions::default());
+        let stop = engine.stop_flag();
+        let iterated = Arc::new(AtomicBool::new(false));
+        let mut observer = Iterated(iterated.clone());
+        let search = std::thread::spawn(move || {
+            let mut chess = Chess::new();
+            engine.search(&mut chess, &SearchLimits { infinite: true, ..SearchLimits::default() }, &mut observer)
+        });
+        while !iterated.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        stop.store(true, Ordering::Relaxed);
+
+        let result = search.join().unwrap();
+        assert!(result.chess_move.is_some());
+        assert!(result.move_from_depth >= 4 && result.move_from_depth < 100);
+    }
+
+    #[test]
+    fn test_threads() {
+        // Helper threads share the table, the result is still a legal move from a completed iteration.
+        let mut chess = Chess::new();
+        chess.setup_fen_string("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let options = SearchOptions { threads: 4, ..SearchOptions::default() };
+        let result = Engine::new(1, options).search(&mut chess, &SearchLimits::depth(6), &mut NoObserver);
+        let m = result.chess_move.unwrap();
+        assert!(chess.possible_moves().iter().any(|p| p.hash() == m.hash()));
+        assert_eq!(result.move_from_depth, 6);
+        assert_eq!(result.pv[0].hash(), m.hash());
+    }
+
+    #[test]
+    fn test_multi_pv() {
+        // Taking the queen with the knight is far ahead of the other lines.
+        let mut chess = Chess::new();
+        chess.setup_fen_string("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 4 4");
+        let options = SearchOptions { multi_pv: 3, ..SearchOptions::default() };
+        let result = Engine::new(1, options).search(&mut chess, &SearchLimits::depth(5), &mut NoObserver);
+
+        assert_eq!(result.lines.len(), 3);
+        assert_eq!(result.lines[0].pv[0].hash(), result.chess_move.as_ref().unwrap().hash());
+        assert_eq!(result.lines[0].score, result.score);
+        assert_eq!(result.chess_move.unwrap().to_string(), "f6h5");
+        for pair in result.lines.windows(2) {
+            assert!(pair[0].score >= pair[1].score);
+            assert_ne!(pair[0].pv[0].hash(), pair[1].pv[0].hash());
+        }
+        // Each line is its own, playable from the root.
+        for line in &result.lines {
+            for m in &line.pv {
+                assert!(chess.possible_moves().contains(m));
+                chess.do_move(m.clone());
+            }
+            for _ in &line.pv {
+                chess.undo_move();
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        depths: Vec<i32>,
+        root_moves: usize,
+        finished: Option<i32>,
+    }
+
+    impl SearchObserver for RecordingObserver {
+        fn iteration_finished(&mut self, info: &IterationInfo) {
+            assert_eq!(info.pv.first().map(|m| m.hash()), info.lines.first().map(|l| l.pv[0].hash()));
+            self.depths.push(info.depth);
+        }
+
+        fn current_move(&mut self, _depth: i32, _m: &Move, _number: usize) {
+            self.root_moves += 1;
+        }
+
+        fn search_finished(&mut self, result: &MoveResult) {
+            self.finished = Some(result.move_from_depth);
+        }
+    }
+
+    #[test]
+    fn test_search_observer() {
+        let mut chess = Chess::new();
+        let mut observer = RecordingObserver::default();
+        Engine::new(1, SearchOptions::default()).search(&mut chess, &SearchLimits::depth(5), &mut observer);
+        assert_eq!(observer.depths, vec![4, 5]);
+        assert!(observer.root_moves >= 40);
+        assert_eq!(observer.finished, Some(5));
+    }
+
+    #[test]
+    fn test_tie_break() {
+        // Bare kings, every move is a draw.
+        let search = |tie_break| {
+            let mut chess = Chess::new();
+            chess.setup_fen_string("8/8/8/3k4/8/8/8/4K3 w - - 0 1");
+            let options = SearchOptions { tie_break, ..SearchOptions::default() };
+            Engine::new(1, options).search(&mut chess, &SearchLimits::depth(4), &mut NoObserver).chess_move.unwrap().to_string()
+        };
+
+        assert_eq!(search(TieBreak::FIRST), search(TieBreak::FIRST));
+        for seed in 0..5 {
+            assert_eq!(search(TieBreak::SEEDED(seed)), search(TieBreak::SEEDED(seed)));
+        }
+        // The seed does pick between the ties.
+        let moves: std::collections::HashSet<_> = (0..20).map(|seed| search(TieBreak::SEEDED(seed))).collect();
+        assert!(moves.len() > 1);
+
+        // From the command line.
+        let args = |args: &[&str]| search_options(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>()).tie_break;
+        assert_eq!(args(&["xo_ai", "--seed", "7"]), TieBreak::SEEDED(7));
+        assert_eq!(args(&["xo_ai"]), TieBreak::RANDOM);
+    }
+
+    #[test]
+    fn test_search_limits() {
+        // The first iteration finds the mate, no need to go on.
+        let mut chess = Chess::new();
+        chess.setup_fen_string("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1");
+        let result = get_next_move(&mut chess, &SearchLimits { mate: Some(1), ..SearchLimits::default() }, &mut NoObserver);
+        assert_eq!(result.move_from_depth, 4);
+        assert_eq!(mate_in::<Chess>(result.score), Some(1));
+
+        let king_move = find_move(&chess, "g1f1");
+        let limits = SearchLimits { depth: Some(4), search_moves: vec![king_move], ..SearchLimits::default() };
+        let mut engine = Engine::new(1, SearchOptions::default());
+        assert_eq!(engine.search(&mut chess, &limits, &mut NoObserver).chess_move.unwrap().to_string(), "g1f1");
+        // The restricted search doesn't stand in for the full one.
+        assert_eq!(engine.search(&mut chess, &SearchLimits::depth(4), &mut NoObserver).chess_move.unwrap().to_string(), "d1d8");
+
+        // Node limited searches are reproducible.
+        let search = || {
+            let mut chess = Chess::new();
+            let options = SearchOptions { tie_break: TieBreak::FIRST, ..SearchOptions::default() };
+            Engine::new(1, options).search(&mut chess, &SearchLimits::nodes(20_000), &mut NoObserver)
+        };
+        let (first, second) = (search(), search());
+        assert!(first.nodes <= 20_001);
+        assert_eq!(first.nodes, second.nodes);
+        assert_eq!(first.move_from_depth, second.move_from_depth);
+        assert_eq!(pv_string(&first.pv), pv_string(&second.pv));
+
+        // Even tiny node limits give a move.
+        for nodes in [1, 100, 1000] {
+            assert!(Engine::new(1, SearchOptions::default()).search(&mut Chess::new(), &SearchLimits::nodes(nodes), &mut NoObserver).chess_move.is_some());
+        }
+    }
+
+    #[test]
+    fn test_engine() {
+        // The second search of a position starts from what the first stored.
+        let options = SearchOptions { tie_break: TieBreak::FIRST, ..SearchOptions::default() };
+        let mut engine = Engine::new(1, options);
+        let mut chess = Chess::new();
+        let first = engine.search(&mut chess, &SearchLimits::depth(6), &mut NoObserver);
+        assert!(engine.hashfull() > 0);
+        let second = engine.search(&mut chess, &SearchLimits::depth(6), &mut NoObserver);
+        assert!(second.nodes < first.nodes);
+        // It is searched again, not taken from the table with a one move line.
+        assert!(second.move_from_depth == 6 && second.pv.len() >= 6);
+
+        engine.new_game();
+        assert_eq!(engine.hashfull(), 0);
+        let fresh = engine.search(&mut chess, &SearchLimits::depth(6), &mut NoObserver);
+        assert_eq!(fresh.nodes, first.nodes);
+    }
+
+    // Tic-tac-toe with only the required search hooks.
+    #[derive(Clone)]
+    struct TicTacToe {
+        cells: [Option<Player>; 9],
+        moves: Vec<usize>,
+    }
+
+    #[derive(Clone, Copy)]
+    struct Cell(usize);
+
+    impl std::fmt::Display for Cell {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl SearchMove for Cell {
+        fn hash(&self) -> u64 { self.0 as u64 }
+    }
+
+    impl TicTacToe {
+        fn winner(&self) -> Option<Player> {
+            let lines = [[0, 1, 2], [3, 4, 5], [6, 7, 8], [0, 3, 6], [1, 4, 7], [2, 5, 8], [0, 4, 8], [2, 4, 6]];
+            lines.iter().find(|l| self.cells[l[0]].is_some() && l.iter().all(|&i| self.cells[i] == self.cells[l[0]])).and_then(|l| self.cells[l[0]])
+        }
+
+        fn play(cells: &[usize]) -> TicTacToe {
+            let mut game = TicTacToe::new();
+            for &i in cells {
+                game.do_move(Cell(i));
+            }
+            game
+        }
+    }
+
+    impl Game for TicTacToe {
+        type MoveType = Cell;
+
+        fn new() -> Self { TicTacToe { cells: [None; 9], moves: vec![] } }
+
+        fn setup_new_game(&mut self) { *self = TicTacToe::new(); }
+
+        fn current_player(&self) -> Player { if self.moves.len().is_multiple_of(2) { PLAYER1 } else { PLAYER2 } }
+
+        fn possible_moves(&self) -> Vec<Cell> {
+            if self.winner().is_some() {
+                return vec![];
+            }
+            (0..9).filter(|&i| self.cells[i].is_none()).map(Cell).collect()
+        }
+
+        fn do_move(&mut self, play: Cell) {
+            self.cells[play.0] = Some(self.current_player());
+            self.moves.push(play.0);
+        }
+
+        fn undo_move(&mut self) -> Cell {
+            let i = self.moves.pop().unwrap();
+            self.cells[i] = None;
+            Cell(i)
+        }
+
+        fn game_state(&self) -> GameState {
+            match self.winner() {
+                Some(PLAYER1) => GameState::PLAYER1WIN,
+                Some(PLAYER2) => GameState::PLAYER2WIN,
+                None if self.moves.len() == 9 => GameState::TIE,
+                None => GameState::PLAYING,
+            }
+        }
+    }
+
+    impl Scored for TicTacToe {
+        type ScoreType = i32;
+        const MAX_INFINITY: i32 = i32::MAX;
+        const MIN_INFINITY: i32 = -i32::MAX;
+        const MAX_SCORE: i32 = 10_000;
+        const NEUTRAL_SCORE: i32 = 0;
+        const MIN_SCORE: i32 = -10_000;
+
+        fn get_score(&self) -> i32 { 0 }
+    }
+
+    impl Searchable for TicTacToe {
+        fn get_hash(&self) -> u64 {
+            self.cells.iter().fold(0, |h, c| h * 3 + c.map_or(0, |p| p as u64 + 1))
+        }
+
+        fn get_game_len(&self) -> usize { self.moves.len() }
+    }
+
+    #[test]
+    fn test_generic_search() {
+        // Perfect play is a draw.
+        let result = get_next_move(&mut TicTacToe::new(), &SearchLimits::depth(9), &mut NoObserver);
+        assert_eq!(result.score, 0);
+
+        // X wins on the top row.
+        let mut game = TicTacToe::play(&[0, 3, 1, 4]);
+        let result = get_next_move(&mut game, &SearchLimits::depth(9), &mut NoObserver);
+        assert_eq!(result.chess_move.unwrap().0, 2);
+        assert_eq!(mate_in::<TicTacToe>(result.score), Some(1));
+
+        // O has to block the top row.
+        let mut game = TicTacToe::play(&[0, 8, 1]);
+        let result = get_next_move(&mut game, &SearchLimits::depth(9), &mut NoObserver);
+        assert_eq!(result.chess_move.unwrap().0, 2);
+    }
+
+    #[test]
+    fn test_mcts() {
+        // X wins on the top row, with random playouts.
+        let mut mcts = Mcts::new(MctsOptions { seed: Some(1), ..MctsOptions::default() });
+        let mut game = TicTacToe::play(&[0, 3, 1, 4]);
+        let result = mcts.search(&mut game, &SearchLimits::nodes(2000));
+        assert_eq!(result.chess_move.unwrap().0, 2);
+        assert!(result.score > 0);
+        assert_eq!(result.nodes, 2000);
+        assert_eq!(result.lines.len(), 5);
+
+        // The tree is kept after a move by X and O's reply.
+        let size = mcts.tree_size();
+        mcts.advance(&Cell(5));
+        game.do_move(Cell(5));
+        mcts.advance(&Cell(2));
+        game.do_move(Cell(2));
+        assert!(mcts.tree_size() > 1 && mcts.tree_size() < size);
+        // X has to block the diagonal.
+        assert_eq!(mcts.search(&mut game, &SearchLimits::nodes(500)).chess_move.unwrap().0, 6);
+
+        // A move that isn't in the tree drops it.
+        mcts.advance(&Cell(0));
+        assert_eq!(mcts.tree_size(), 0);
+
+        // Takes the free queen on chess, with eval playouts.
+        let mut mcts = Mcts::new(MctsOptions { playout: Playout::EVAL { plies: 0, evaluate: score_evaluation }, seed: Some(1), ..MctsOptions::default() });
+        let mut chess = Chess::new();
+        chess.setup_fen_string("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1");
+        let result = mcts.search(&mut chess, &SearchLimits::nodes(1000));
+        assert_eq!(result.chess_move.unwrap().to_string(), "d2d5");
+    }
+
+    #[test]
+    fn test_mate_solver() {
+        let mut chess = Chess::new();
+        chess.setup_fen_string("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1");
+        let result = solve_mate(&mut chess, 3, 16);
+        assert_eq!(result.proof, Proof::PROVEN);
+        assert_eq!(result.mate_in(), Some(2));
+        assert_eq!(result.pv[0].to_string(), "a1a6");
+
+        // The line is mate, and the main search agrees.
+        let search = Engine::new(1, SearchOptions::default()).search(&mut chess, &SearchLimits::depth(4), &mut NoObserver);
+        assert_eq!(mate_in::<Chess>(search.score), result.mate_in());
+        for m in &result.pv {
+            chess.do_move(m.clone());
+        }
+        assert!(chess.possible_moves().is_empty() && chess.is_in_check());
+
+        // Two kings can't mate.
+        chess.setup_fen_string("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(solve_mate(&mut chess, 2, 16).proof, Proof::DISPROVEN);
+
+        // Out of memory.
+        chess.setup_fen_string("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1");
+        assert_eq!(solve_mate(&mut chess, 3, 0).proof, Proof::UNKNOWN);
+    }
+
+    #[test]
+    fn test_search_trace() {
+        let mut chess = Chess::new();
+        chess.setup_fen_string("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let tt = TranspositionTable::new(1);
+        let mut ctx = SearchContext::new(&tt);
+        ctx.trace = Some(SearchTrace::new(1));
+        let score = alpha_beta(&mut chess, 3, Chess::MIN_INFINITY, Chess::MAX_INFINITY, &mut ctx).unwrap();
+
+        let trace = ctx.trace.unwrap();
+        let root = &trace.nodes[0];
+        assert_eq!((root.m.as_str(), root.depth, root.score, root.nodes), ("root", 3, Some(score), ctx.call_count));
+        assert_eq!(root.cutoff, Cutoff::NONE);
+        // Only the root and its moves, each searched at least once.
+        assert!(trace.nodes.iter().skip(1).all(|n| n.ply == 1 && n.children.is_empty() && n.score.is_some()));
+        assert!(root.children.len() >= chess.possible_moves().len());
+        assert!(root.children.iter().map(|&c| trace.nodes[c].nodes).sum::<u64>() < root.nodes);
+
+        assert!(trace.to_json().starts_with("{\"move\":\"root\",\"ply\":0,\"depth\":3,"));
+        assert!(trace.to_dot().contains("n0 -> n1;"));
+
+        // As a search option, the last iteration's tree from the root.
+        let options = SearchOptions { trace_ply: Some(1), ..SearchOptions::default() };
+        let result = Engine::new(1, options).search(&mut chess, &SearchLimits::depth(4), &mut NoObserver);
+        let trace = result.trace.unwrap();
+        let root = &trace.nodes[0];
+        assert_eq!((root.m.as_str(), root.depth, root.score), ("root", 4, Some(result.score)));
+        assert!(root.children.len() >= chess.possible_moves().len());
+        assert!(trace.nodes.iter().skip(1).all(|n| n.ply == 1));
+
+        // Strings are escaped.
+        let mut trace = SearchTrace::new(0);
+        trace.enter(0, Some(&"a\"b\\"), 1, -1, 1, 0);
+        trace.exit(Some(0), 1);
+        assert!(trace.to_json().starts_with(r#"{"move":"a\"b\\","#));
+        assert!(trace.to_dot().contains(r#"n0 [label="a\"b\\\ndepth 1"#));
+    }
+
     #[test]
     fn test_move_to_string() {
         let mut chess = Chess::new();
@@ -114,4 +838,38 @@ mod tests {
         }
     }
 
+    // Compares perft speed of the magic and pext slider lookups. Run with:
+    // cargo test --release --features pext bench_slider_lookup -- --ignored --nocapture
+    #[cfg(feature = "pext")]
+    #[test]
+    #[ignore]
+    fn bench_slider_lookup() {
+        use std::time::Instant;
+        use crate::move_generation::pext::{set_pext_enabled, pext_enabled};
+
+        let positions = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 5),
+            ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", 4),
+            ("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1", 4),
+        ];
+
+        for (name, use_pext) in [("magic", false), ("pext", true)].iter().copied() {
+            set_pext_enabled(use_pext);
+            if pext_enabled() != use_pext {
+                println!("{}: not supported on this cpu", name);
+                continue;
+            }
+
+            let start = Instant::now();
+            let mut nodes = 0;
+            for (fen, depth) in positions.iter().copied() {
+                let mut chess = Chess::new();
+                chess.setup_fen_string(fen);
+                nodes += count_positions(&mut chess, depth);
+            }
+            let elapsed = start.elapsed();
+            println!("{}: Nodes: {}, Time: {:?}, NPS: {}", name, nodes, elapsed, (nodes as f64 / elapsed.as_secs_f64()) as u64);
+        }
+        set_pext_enabled(true);
+    }
 }
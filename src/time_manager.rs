@@ -0,0 +1,108 @@
+use std::time::{Duration, Instant};
+
+// Kept in hand for the move to reach the front-end.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+// Moves the remaining time is split over, when the time control doesn't say.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// The hard limit is this many times the soft limit, if the clock allows.
+const HARD_LIMIT_FACTOR: u32 = 4;
+// A score drop of this many centipawns from the last iteration is worth thinking longer about.
+const SCORE_DROP: i32 = 30;
+
+// The clock for one move. Nothing set means no time limit.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimeControl {
+    // Time left on the clock of the player to move.
+    pub remaining: Option<Duration>,
+    // Added to the clock after every move.
+    pub increment: Duration,
+    // Moves until the next time control, otherwise the remaining time is for the rest of the game.
+    pub moves_to_go: Option<u32>,
+    // Exactly this long for the move, ignoring the clock.
+    pub movetime: Option<Duration>,
+}
+
+impl TimeControl {
+    pub fn movetime(movetime: Duration) -> TimeControl {
+        TimeControl { movetime: Some(movetime), ..TimeControl::default() }
+    }
+}
+
+// Decides how long a search goes on.
+// The soft limit is checked between iterations, and is stretched when the best move is unstable or the score drops.
+// The hard limit aborts the search in the middle of an iteration.
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+
+    last_best_move: Option<u64>,
+    last_score: Option<i32>,
+    // Decaying count of best move changes between iterations.
+    instability: f64,
+    score_dropped: bool,
+}
+
+impl TimeManager {
+    pub fn new(time_control: TimeControl) -> TimeManager {
+        let (soft_limit, hard_limit) = match time_control {
+            TimeControl { movetime: Some(movetime), .. } => {
+                let limit = movetime.saturating_sub(MOVE_OVERHEAD);
+                (Some(limit), Some(limit))
+            }
+            TimeControl { remaining: Some(remaining), increment, moves_to_go, .. } => {
+                let available = remaining.saturating_sub(MOVE_OVERHEAD);
+                let moves_to_go = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+                let soft = (available / moves_to_go + increment * 3 / 4).min(available);
+                let hard = (soft * HARD_LIMIT_FACTOR).min(available * 3 / 4).max(soft);
+                (Some(soft), Some(hard))
+            }
+            _ => (None, None)
+        };
+
+        TimeManager {
+            start: Instant::now(),
+            soft_limit,
+            hard_limit,
+            last_best_move: None,
+            last_score: None,
+            instability: 0.0,
+            score_dropped: false,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn hard_limit(&self) -> Option<Duration> {
+        self.hard_limit
+    }
+
+    // Record a completed iteration's best move (as SearchMove::hash) and score.
+    pub fn iteration_finished(&mut self, best_move: u64, score: i32) {
+        self.instability /= 2.0;
+        if self.last_best_move.is_some_and(|m| m != best_move) {
+            self.instability += 1.0;
+        }
+        self.score_dropped = self.last_score.is_some_and(|s| score <= s - SCORE_DROP);
+        self.last_best_move = Some(best_move);
+        self.last_score = Some(score);
+    }
+
+    // The soft limit, stretched for the last iterations.
+    pub fn soft_limit(&self) -> Option<Duration> {
+        let soft = self.soft_limit?;
+        let mut factor = 1.0 + self.instability / 2.0;
+        if self.score_dropped {
+            factor *= 1.5;
+        }
+        let soft = soft.mul_f64(factor);
+        Some(self.hard_limit.map_or(soft, |hard| soft.min(hard)))
+    }
+
+    // Whether to stop instead of starting another iteration.
+    pub fn should_stop(&self) -> bool {
+        self.soft_limit().is_some_and(|soft| self.elapsed() >= soft)
+    }
+}
@@ -0,0 +1,137 @@
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const DEFAULT_HASH_MB: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bound { EXACT = 1, LOWER = 2, UPPER = 3 }
+
+#[derive(Clone, Copy, Debug)]
+pub struct TTEntry {
+    pub key: u64,
+    pub depth: i32,
+    pub score: i32,
+    pub bound: Bound,
+    // Move::hash of the best move found, if any.
+    pub best_move: Option<u64>,
+    // The search the entry was written in.
+    pub age: u8,
+}
+
+// Move::hash never has all bits lit.
+const NO_MOVE: u64 = u64::MAX;
+
+// An entry packed into atomics, so the search threads can share the table without locks.
+// check is the key xor'ed with the other two, an entry torn by threads writing at once doesn't match its key.
+#[derive(Default)]
+struct Slot {
+    check: AtomicU64,
+    best_move: AtomicU64,
+    // Score, depth, bound and age.
+    data: AtomicU64,
+}
+
+impl TTEntry {
+    fn pack(&self) -> (u64, u64) {
+        let data = self.score as u32 as u64
+            | (self.depth as i16 as u16 as u64) << 32
+            | (self.bound as u64) << 48
+            | (self.age as u64) << 56;
+        (self.best_move.unwrap_or(NO_MOVE), data)
+    }
+
+    fn unpack(key: u64, best_move: u64, data: u64) -> Option<TTEntry> {
+        let bound = match (data >> 48) as u8 {
+            1 => Bound::EXACT,
+            2 => Bound::LOWER,
+            3 => Bound::UPPER,
+            // Never written.
+            _ => return None
+        };
+        Some(TTEntry {
+            key,
+            depth: (data >> 32) as u16 as i16 as i32,
+            score: data as u32 as i32,
+            bound,
+            best_move: if best_move == NO_MOVE { None } else { Some(best_move) },
+            age: (data >> 56) as u8,
+        })
+    }
+}
+
+// Fixed size hash table of searched positions, indexed by zobrist hash.
+pub struct TranspositionTable {
+    entries: Vec<Slot>,
+    age: u8,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let max_entries = (size_mb * 1024 * 1024 / size_of::<Slot>()).max(1);
+        // Round down to a power of two so the index is a mask of the key.
+        let len = 1 << (63 - (max_entries as u64).leading_zeros());
+        TranspositionTable {
+            entries: (0..len).map(|_| Slot::default()).collect(),
+            age: 0,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Forget everything, for a new game.
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|slot| *slot = Slot::default());
+        self.age = 0;
+    }
+
+    // Call at the start of every search, older entries get replaced first.
+    pub fn new_search(&mut self) {
+        self.age = self.age.wrapping_add(1);
+    }
+
+    #[inline]
+    fn slot(&self, key: u64) -> &Slot {
+        &self.entries[key as usize & (self.entries.len() - 1)]
+    }
+
+    // The entry in the slot, None if it was never written or is torn.
+    fn read(slot: &Slot) -> Option<TTEntry> {
+        let check = slot.check.load(Ordering::Relaxed);
+        let best_move = slot.best_move.load(Ordering::Relaxed);
+        let data = slot.data.load(Ordering::Relaxed);
+        TTEntry::unpack(check ^ best_move ^ data, best_move, data)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TTEntry> {
+        TranspositionTable::read(self.slot(key)).filter(|e| e.key == key)
+    }
+
+    pub fn store(&self, key: u64, depth: i32, score: i32, bound: Bound, best_move: Option<u64>) {
+        let slot = self.slot(key);
+        let age = self.age;
+        let old = TranspositionTable::read(slot);
+        let replace = match &old {
+            None => true,
+            Some(e) => e.key == key || e.age != age || depth >= e.depth
+        };
+
+        if replace {
+            // Keep the old best move when we have none for the same position.
+            let best_move = best_move.or_else(|| old.filter(|e| e.key == key).and_then(|e| e.best_move));
+            let (best_move, data) = TTEntry { key, depth, score, bound, best_move, age }.pack();
+            slot.check.store(key ^ best_move ^ data, Ordering::Relaxed);
+            slot.best_move.store(best_move, Ordering::Relaxed);
+            slot.data.store(data, Ordering::Relaxed);
+        }
+    }
+
+    // Permille of entries written during the current search, sampled from the start of the table.
+    pub fn hashfull(&self) -> usize {
+        let sample = self.entries.len().min(1000);
+        let used = self.entries[..sample].iter().filter(|s| matches!(TranspositionTable::read(s), Some(e) if e.age == self.age)).count();
+        used * 1000 / sample
+    }
+}
@@ -39,11 +39,14 @@ pub trait Game {
     fn console_draw(&self) {}
 }
 
+// The search scores mates from MAX_SCORE and MIN_SCORE, so MIN_SCORE has to be -MAX_SCORE and get_score has to stay
+// below MAX_SCORE - 1000, which is taken as a mate within 1000 plies.
 pub trait Scored {
     type ScoreType: Ord + Clone + Eq + PartialEq + Copy + Debug + Display;
 
     const MAX_INFINITY: Self::ScoreType;
     const MIN_INFINITY: Self::ScoreType;
+    // A won game, beyond any evaluation. Searches count down from it the further away the win is.
     const MAX_SCORE: Self::ScoreType;
     const NEUTRAL_SCORE: Self::ScoreType;
     const MIN_SCORE: Self::ScoreType;
@@ -51,3 +54,67 @@ pub trait Scored {
     // player 1 score is positive player 2 score is negative.
     fn get_score(&self) -> Self::ScoreType;
 }
+
+// Hooks the search uses on moves. Only hash is required, the defaults suit a game without captures.
+pub trait SearchMove: Clone + Display + Send {
+    // Tells the moves of a position apart, for the transposition table and move ordering. Never u64::MAX.
+    fn hash(&self) -> u64;
+
+    // Moves that don't change the material. Quiet moves are ordered by history and may be reduced or pruned,
+    // the others are ordered by mvv_lva.
+    fn is_quiet(&self) -> bool { true }
+
+    fn is_capture(&self) -> bool { !self.is_quiet() }
+
+    // Searched in quiescence, until the position is quiet.
+    fn is_tactical(&self) -> bool { !self.is_quiet() }
+
+    // Passing the turn, for null move pruning.
+    fn is_null(&self) -> bool { false }
+
+    // Material won by the move, for delta pruning.
+    fn material_gain(&self) -> i32 { 0 }
+
+    // Sort key of non-quiet moves, higher first.
+    fn mvv_lva(&self) -> i32 { 0 }
+
+    // From and to squares below 64, the history tables are indexed by them.
+    fn squares(&self) -> (usize, usize) {
+        let hash = self.hash();
+        (hash as usize % 64, (hash >> 6) as usize % 64)
+    }
+}
+
+// What the search needs from a game on top of Game and Scored. Implementing the required methods is enough for the
+// full search, the others make it stronger for games they apply to.
+pub trait Searchable: Game<MoveType: SearchMove> + Scored<ScoreType = i32> + Clone + Send {
+    // Hash of the position, for the transposition table.
+    fn get_hash(&self) -> u64;
+
+    // Moves played so far, the search measures plies from the root with it.
+    fn get_game_len(&self) -> usize;
+
+    // In check, stand pat isn't allowed and moves aren't pruned.
+    fn is_in_check(&self) -> bool { false }
+
+    // The player to move has no moves, whether that lost the game. Otherwise it's a draw.
+    fn is_lost(&self) -> bool {
+        matches!(self.game_state(), GameState::PLAYER1WIN | GameState::PLAYER2WIN)
+    }
+
+    // Drawn by a rule, like repetition, even though there are moves left.
+    fn is_draw(&self) -> bool { false }
+
+    fn last_move(&self) -> Option<&Self::MoveType> { None }
+
+    // Whether passing the turn is a fair test of the position, see do_null_move.
+    fn can_null_move(&self) -> bool { false }
+
+    // Pass the turn, only called when can_null_move.
+    fn do_null_move(&mut self) {}
+
+    fn undo_null_move(&mut self) {}
+
+    // Material the player to move wins or loses with the capture m, once all exchanges on the square are done.
+    fn see(&self, m: &Self::MoveType) -> i32 { m.material_gain() }
+}
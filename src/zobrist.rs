@@ -0,0 +1,61 @@
+// Zobrist keys for hashing chess positions. Generated at compile time from a fixed seed.
+
+pub struct ZobristKeys {
+    // Indexed by player, piece type and square index.
+    pub pieces: [[[u64; 64]; 6]; 2],
+    // Lit bits of the castle memory, by square index.
+    pub castle: [u64; 64],
+    // The x coordinate of the en passant square.
+    pub en_passant: [u64; 8],
+    pub player2_to_move: u64,
+}
+
+// splitmix64
+const fn next_key(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+const fn generate_keys() -> ZobristKeys {
+    let mut state = 0x05EE_D0FC_4E55_u64;
+    let mut keys = ZobristKeys {
+        pieces: [[[0; 64]; 6]; 2],
+        castle: [0; 64],
+        en_passant: [0; 8],
+        player2_to_move: 0,
+    };
+
+    let mut player = 0;
+    while player < 2 {
+        let mut piece_type = 0;
+        while piece_type < 6 {
+            let mut index = 0;
+            while index < 64 {
+                keys.pieces[player][piece_type][index] = next_key(&mut state);
+                index += 1;
+            }
+            piece_type += 1;
+        }
+        player += 1;
+    }
+
+    let mut index = 0;
+    while index < 64 {
+        keys.castle[index] = next_key(&mut state);
+        index += 1;
+    }
+
+    let mut x = 0;
+    while x < 8 {
+        keys.en_passant[x] = next_key(&mut state);
+        x += 1;
+    }
+
+    keys.player2_to_move = next_key(&mut state);
+    keys
+}
+
+pub static ZOBRIST: ZobristKeys = generate_keys();